@@ -0,0 +1,466 @@
+use axum::body::Bytes;
+use lru::LruCache;
+use reqwest::header::{self, HeaderMap, HeaderValue};
+use serde::Serialize;
+use std::fmt::Debug;
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use url::Url;
+
+/// Caching directives of an origin response.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Freshness {
+    /// `false` for `no-store` and `private` responses.
+    pub storable: bool,
+    pub max_age: Option<Duration>,
+    pub stale_while_revalidate: Option<Duration>,
+}
+
+impl Freshness {
+    /// Reads `Cache-Control`, falling back to `Expires` (relative to `Date`)
+    /// for the freshness lifetime.
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let mut storable = true;
+        let mut no_cache = false;
+        let mut max_age = None;
+        let mut shared_max_age = None;
+        let mut stale_while_revalidate = None;
+
+        let directives = headers
+            .get_all(header::CACHE_CONTROL)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','));
+        for directive in directives {
+            let (name, value) = match directive.split_once('=') {
+                Some((name, value)) => (name.trim(), Some(value.trim().trim_matches('"'))),
+                None => (directive.trim(), None),
+            };
+            let seconds = value
+                .and_then(|value| value.parse().ok())
+                .map(Duration::from_secs);
+            match name.to_ascii_lowercase().as_str() {
+                "no-store" | "private" => storable = false,
+                "no-cache" => no_cache = true,
+                "max-age" => max_age = seconds,
+                "s-maxage" => shared_max_age = seconds,
+                "stale-while-revalidate" => stale_while_revalidate = seconds,
+                _ => {}
+            }
+        }
+
+        let max_age = if no_cache {
+            Some(Duration::ZERO)
+        } else {
+            shared_max_age.or(max_age).or_else(|| expires(headers))
+        };
+        Freshness {
+            storable,
+            max_age,
+            stale_while_revalidate,
+        }
+    }
+}
+
+fn expires(headers: &HeaderMap) -> Option<Duration> {
+    let date = |name| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(httpdate::parse_http_date)
+    };
+    // An invalid `Expires` means the response is already stale.
+    let expires = date(header::EXPIRES)?.ok()?;
+    let now = date(header::DATE)
+        .and_then(Result::ok)
+        .unwrap_or_else(SystemTime::now);
+    Some(expires.duration_since(now).unwrap_or(Duration::ZERO))
+}
+
+/// Validators of an origin response, sent back to revalidate the page once
+/// its cached result has expired.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Validators {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+impl Validators {
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let value = |name| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.to_string())
+        };
+        Validators {
+            etag: value(header::ETAG),
+            last_modified: value(header::LAST_MODIFIED),
+        }
+    }
+
+    /// The `If-None-Match` and `If-Modified-Since` headers of a conditional
+    /// request.
+    pub fn conditional_headers(&self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        let pairs = [
+            (header::IF_NONE_MATCH, &self.etag),
+            (header::IF_MODIFIED_SINCE, &self.last_modified),
+        ];
+        for (name, value) in pairs {
+            if let Some(value) = value.as_deref().and_then(|v| HeaderValue::from_str(v).ok()) {
+                headers.insert(name, value);
+            }
+        }
+        headers
+    }
+
+    /// Keeps the validators of `previous` that a `304 Not Modified` response
+    /// did not repeat.
+    pub fn or(self, previous: &Validators) -> Validators {
+        Validators {
+            etag: self.etag.or_else(|| previous.etag.clone()),
+            last_modified: self
+                .last_modified
+                .or_else(|| previous.last_modified.clone()),
+        }
+    }
+}
+
+pub const DEFAULT_CAPACITY: usize = 1000;
+
+#[derive(Debug, Clone)]
+pub struct CacheConfig {
+    pub min_ttl: Duration,
+    pub max_ttl: Duration,
+    /// How long an expired entry may still be served while it is refreshed,
+    /// unless the origin sets `stale-while-revalidate`.
+    pub stale_ttl: Duration,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        CacheConfig {
+            min_ttl: Duration::from_secs(60),
+            max_ttl: Duration::from_secs(24 * 60 * 60),
+            stale_ttl: Duration::from_secs(60 * 60),
+        }
+    }
+}
+
+/// A serialized extraction result with its expiry times and the validators
+/// of the page it was extracted from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CachedPage {
+    pub body: Bytes,
+    pub fresh_until: SystemTime,
+    pub stale_until: SystemTime,
+    pub validators: Validators,
+}
+
+/// Where cached extraction results are kept. Failures are logged by the
+/// store and treated as cache misses.
+pub trait CacheStore: Send + Sync + Debug {
+    fn get(&self, key: &str) -> Option<CachedPage>;
+    fn put(&self, key: &str, page: CachedPage);
+    fn remove(&self, key: &str);
+}
+
+/// Keeps up to `capacity` entries in memory, evicting the least recently
+/// used one.
+#[derive(Debug)]
+pub struct MemoryStore {
+    entries: Option<Mutex<LruCache<String, CachedPage>>>,
+}
+
+impl MemoryStore {
+    /// A `capacity` of 0 disables caching.
+    pub fn new(capacity: usize) -> Self {
+        MemoryStore {
+            entries: NonZeroUsize::new(capacity)
+                .map(|capacity| Mutex::new(LruCache::new(capacity))),
+        }
+    }
+}
+
+impl CacheStore for MemoryStore {
+    fn get(&self, key: &str) -> Option<CachedPage> {
+        self.entries.as_ref()?.lock().unwrap().get(key).cloned()
+    }
+
+    fn put(&self, key: &str, page: CachedPage) {
+        if let Some(entries) = &self.entries {
+            entries.lock().unwrap().put(key.to_string(), page);
+        }
+    }
+
+    fn remove(&self, key: &str) {
+        if let Some(entries) = &self.entries {
+            entries.lock().unwrap().pop(key);
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Lookup {
+    Fresh(Bytes),
+    /// Expired, but still within its stale-while-revalidate window. The
+    /// whole entry is returned so that it can be revalidated.
+    Stale(CachedPage),
+}
+
+/// A cache of serialized extraction results, kept in a [`CacheStore`].
+///
+/// Stores may block on disk or on locks held by other processes, so they
+/// are called from the blocking thread pool.
+#[derive(Debug)]
+pub struct MetadataCache {
+    store: Arc<dyn CacheStore>,
+    config: CacheConfig,
+}
+
+impl MetadataCache {
+    pub fn new(store: Box<dyn CacheStore>, config: CacheConfig) -> Self {
+        MetadataCache {
+            store: Arc::from(store),
+            config,
+        }
+    }
+
+    pub async fn get(&self, key: &str) -> Option<Lookup> {
+        let key = key.to_string();
+        self.blocking(move |store| {
+            let page = store.get(&key)?;
+            let now = SystemTime::now();
+            if now < page.fresh_until {
+                Some(Lookup::Fresh(page.body))
+            } else if now < page.stale_until {
+                Some(Lookup::Stale(page))
+            } else {
+                store.remove(&key);
+                None
+            }
+        })
+        .await
+    }
+
+    /// Stores a response body for as long as `freshness` allows, within the
+    /// configured bounds.
+    pub async fn insert(
+        &self,
+        key: &str,
+        body: Bytes,
+        freshness: Freshness,
+        validators: Validators,
+    ) {
+        if !freshness.storable {
+            return;
+        }
+        let ttl = freshness
+            .max_age
+            .unwrap_or(self.config.min_ttl)
+            .max(self.config.min_ttl)
+            .min(self.config.max_ttl);
+        let stale_ttl = freshness
+            .stale_while_revalidate
+            .unwrap_or(self.config.stale_ttl);
+        let fresh_until = SystemTime::now() + ttl;
+        let page = CachedPage {
+            body,
+            fresh_until,
+            stale_until: fresh_until + stale_ttl,
+            validators,
+        };
+        let key = key.to_string();
+        self.blocking(move |store| store.put(&key, page)).await;
+    }
+
+    async fn blocking<T: Send + 'static>(
+        &self,
+        call: impl FnOnce(&dyn CacheStore) -> T + Send + 'static,
+    ) -> T {
+        let store = self.store.clone();
+        tokio::task::spawn_blocking(move || call(store.as_ref()))
+            .await
+            .unwrap_or_else(|err| std::panic::resume_unwind(err.into_panic()))
+    }
+}
+
+impl Default for MetadataCache {
+    fn default() -> Self {
+        MetadataCache::new(
+            Box::new(MemoryStore::new(DEFAULT_CAPACITY)),
+            CacheConfig::default(),
+        )
+    }
+}
+
+/// The cache key of an extraction: the URL without its fragment and with
+/// sorted query parameters, followed by the extraction options.
+pub fn key(url: &str, options: &impl Serialize) -> String {
+    let url = match Url::parse(url) {
+        Ok(mut url) => {
+            url.set_fragment(None);
+            let mut pairs: Vec<(String, String)> = url.query_pairs().into_owned().collect();
+            if pairs.is_empty() {
+                url.set_query(None);
+            } else {
+                pairs.sort();
+                url.query_pairs_mut().clear().extend_pairs(pairs);
+            }
+            url.to_string()
+        }
+        Err(_) => url.to_string(),
+    };
+    format!(
+        "{} {}",
+        url,
+        serde_json::to_string(options).unwrap_or_default()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    fn headers(pairs: &[(header::HeaderName, &str)]) -> HeaderMap {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.clone(), HeaderValue::from_str(value).unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn test_freshness_from_headers() {
+        let freshness = Freshness::from_headers(&headers(&[(
+            header::CACHE_CONTROL,
+            "public, max-age=300, s-maxage=600, stale-while-revalidate=30",
+        )]));
+        assert!(freshness.storable);
+        assert_eq!(freshness.max_age, Some(Duration::from_secs(600)));
+        assert_eq!(
+            freshness.stale_while_revalidate,
+            Some(Duration::from_secs(30))
+        );
+
+        let freshness = Freshness::from_headers(&headers(&[
+            (header::DATE, "Sun, 06 Nov 1994 08:49:37 GMT"),
+            (header::EXPIRES, "Sun, 06 Nov 1994 09:49:37 GMT"),
+        ]));
+        assert_eq!(freshness.max_age, Some(Duration::from_secs(3600)));
+
+        let freshness = Freshness::from_headers(&headers(&[(header::EXPIRES, "0")]));
+        assert_eq!(freshness.max_age, None);
+
+        let freshness = Freshness::from_headers(&headers(&[(
+            header::CACHE_CONTROL,
+            "no-cache, max-age=300",
+        )]));
+        assert_eq!(freshness.max_age, Some(Duration::ZERO));
+
+        assert!(
+            !Freshness::from_headers(&headers(&[(header::CACHE_CONTROL, "no-store")])).storable
+        );
+        assert!(!Freshness::from_headers(&headers(&[(header::CACHE_CONTROL, "private")])).storable);
+    }
+
+    #[test]
+    fn test_validators() {
+        let validators = Validators::from_headers(&headers(&[
+            (header::ETAG, "W/\"abc\""),
+            (header::LAST_MODIFIED, "Sun, 06 Nov 1994 08:49:37 GMT"),
+        ]));
+        let conditional = validators.conditional_headers();
+        assert_eq!(conditional[header::IF_NONE_MATCH], "W/\"abc\"");
+        assert_eq!(
+            conditional[header::IF_MODIFIED_SINCE],
+            "Sun, 06 Nov 1994 08:49:37 GMT"
+        );
+
+        let updated = Validators::from_headers(&headers(&[(header::ETAG, "\"def\"")]));
+        assert_eq!(
+            updated.or(&validators),
+            Validators {
+                etag: Some("\"def\"".to_string()),
+                last_modified: validators.last_modified.clone(),
+            }
+        );
+        assert!(Validators::default().conditional_headers().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_metadata_cache() {
+        let cache = MetadataCache::new(
+            Box::new(MemoryStore::new(2)),
+            CacheConfig {
+                min_ttl: Duration::ZERO,
+                max_ttl: Duration::from_secs(60),
+                stale_ttl: Duration::from_secs(60),
+            },
+        );
+        let freshness = |max_age| Freshness {
+            storable: true,
+            max_age: Some(Duration::from_secs(max_age)),
+            stale_while_revalidate: None,
+        };
+
+        let validators = Validators {
+            etag: Some("\"v1\"".to_string()),
+            last_modified: None,
+        };
+        cache
+            .insert(
+                "a",
+                Bytes::from("a"),
+                freshness(3600),
+                Validators::default(),
+            )
+            .await;
+        cache
+            .insert("b", Bytes::from("b"), freshness(0), validators.clone())
+            .await;
+        assert_eq!(cache.get("a").await, Some(Lookup::Fresh(Bytes::from("a"))));
+        match cache.get("b").await {
+            Some(Lookup::Stale(page)) => {
+                assert_eq!(page.body, Bytes::from("b"));
+                assert_eq!(page.validators, validators);
+            }
+            lookup => panic!("unexpected lookup: {:?}", lookup),
+        }
+
+        // "a" was used most recently, so "b" is evicted.
+        cache.get("a").await;
+        cache
+            .insert("c", Bytes::from("c"), freshness(60), Validators::default())
+            .await;
+        assert_eq!(cache.get("b").await, None);
+        assert!(cache.get("c").await.is_some());
+
+        cache
+            .insert(
+                "d",
+                Bytes::from("d"),
+                Freshness {
+                    storable: false,
+                    ..freshness(60)
+                },
+                Validators::default(),
+            )
+            .await;
+        assert_eq!(cache.get("d").await, None);
+    }
+
+    #[test]
+    fn test_key() {
+        assert_eq!(
+            key("HTTPS://Example.com:443/page?b=2&a=1#top", &()),
+            key("https://example.com/page?a=1&b=2", &())
+        );
+        assert_ne!(
+            key("https://example.com/", &1),
+            key("https://example.com/", &2)
+        );
+    }
+}
@@ -0,0 +1,184 @@
+use encoding_rs::Encoding;
+use html5ever::parse_document;
+use html5ever::tendril::{StrTendril, TendrilSink};
+use markup5ever_rcdom::{Handle, NodeData, RcDom};
+use reqwest::{header, Response};
+use tokio::sync::mpsc;
+
+use crate::{encoding::HtmlDecoder, errors::ServerError};
+
+const HTML_CONTENT_TYPES: [&str; 2] = ["text/html", "application/xhtml+xml"];
+
+/// Chunks read ahead of the parser.
+const READ_AHEAD: usize = 1;
+
+/// Streams the body of an HTML response into html5ever and passes the parsed
+/// document to `extract`.
+///
+/// Unless `full_document` is set, reading stops once the parser has moved
+/// past `<head>`, and whatever part of the body arrived with the head is
+/// dropped, so the result does not depend on how the body was chunked.
+/// Responses that declare a non-HTML `Content-Type` are rejected before the
+/// body is read, and bodies longer than `max_body_size` are aborted.
+///
+/// `RcDom` can't be held across an `.await`, so the parser and `extract` run
+/// on a blocking thread fed through a channel.
+pub async fn parse_response<T, F>(
+    mut response: Response,
+    max_body_size: usize,
+    full_document: bool,
+    extract: F,
+) -> Result<(T, &'static Encoding), ServerError>
+where
+    T: Send + 'static,
+    F: FnOnce(&RcDom) -> T + Send + 'static,
+{
+    let content_type = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.to_string());
+    if let Some(content_type) = &content_type {
+        let mime_type = content_type
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase();
+        if !HTML_CONTENT_TYPES.contains(&mime_type.as_str()) {
+            return Err(ServerError::UnsupportedContentType(mime_type));
+        }
+    }
+
+    let (sender, receiver) = mpsc::channel(READ_AHEAD);
+    let parser = tokio::task::spawn_blocking(move || {
+        let (dom, encoding) = parse_chunks(receiver, content_type.as_deref(), full_document);
+        (extract(&dom), encoding)
+    });
+
+    let mut size = 0;
+    while let Some(chunk) = response.chunk().await? {
+        // The parser hangs up once it has seen enough of the document.
+        if sender.is_closed() {
+            break;
+        }
+        size += chunk.len();
+        if size > max_body_size {
+            return Err(ServerError::BodyTooLarge(max_body_size));
+        }
+        if sender.send(chunk).await.is_err() {
+            break;
+        }
+    }
+    drop(sender);
+
+    Ok(parser
+        .await
+        .unwrap_or_else(|err| std::panic::resume_unwind(err.into_panic())))
+}
+
+fn parse_chunks(
+    mut receiver: mpsc::Receiver<impl AsRef<[u8]>>,
+    content_type: Option<&str>,
+    full_document: bool,
+) -> (RcDom, &'static Encoding) {
+    let mut parser = parse_document(RcDom::default(), Default::default());
+    let mut decoder = HtmlDecoder::new(content_type);
+
+    while let Some(chunk) = receiver.blocking_recv() {
+        parser.process(StrTendril::from(decoder.decode(chunk.as_ref(), false)));
+        if !full_document && is_past_head(&parser.tokenizer.sink.sink.document) {
+            break;
+        }
+    }
+    receiver.close();
+    parser.process(StrTendril::from(decoder.decode(&[], true)));
+
+    let encoding = decoder.encoding().unwrap_or(encoding_rs::UTF_8);
+    let dom = parser.finish();
+    if !full_document {
+        for body in body_elements(&dom.document) {
+            body.children.borrow_mut().clear();
+        }
+    }
+    (dom, encoding)
+}
+
+/// Whether the tree builder has closed `<head>` and started the body.
+fn is_past_head(document: &Handle) -> bool {
+    !body_elements(document).is_empty()
+}
+
+/// The `<body>` or `<frameset>` of the document, once it has been started.
+fn body_elements(document: &Handle) -> Vec<Handle> {
+    let mut bodies = Vec::new();
+    for html in document.children.borrow().iter() {
+        bodies.extend(
+            html.children
+                .borrow()
+                .iter()
+                .filter(|child| {
+                    matches!(
+                        &child.data,
+                        NodeData::Element { name, .. }
+                            if matches!(name.local.as_ref(), "body" | "frameset")
+                    )
+                })
+                .cloned(),
+        );
+    }
+    bodies
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(chunks: &[&str], full_document: bool) -> RcDom {
+        let (sender, receiver) = mpsc::channel(chunks.len());
+        for chunk in chunks {
+            sender.try_send(chunk.as_bytes().to_vec()).unwrap();
+        }
+        drop(sender);
+        parse_chunks(receiver, Some("text/html"), full_document).0
+    }
+
+    fn body_text(dom: &RcDom) -> String {
+        fn text(handle: &Handle, out: &mut String) {
+            if let NodeData::Text { ref contents } = handle.data {
+                out.push_str(&contents.borrow());
+            }
+            for child in handle.children.borrow().iter() {
+                text(child, out);
+            }
+        }
+        let mut out = String::new();
+        for body in body_elements(&dom.document) {
+            text(&body, &mut out);
+        }
+        out
+    }
+
+    #[test]
+    fn test_head_only_ignores_chunking() {
+        let page = "<html><head><title>Title</title></head>\
+                    <body><h1>Heading</h1><p>First</p><p>Second</p></body></html>";
+        let split = page.find("<p>").unwrap();
+        let chunkings = [
+            vec![page],
+            vec![&page[..split], &page[split..]],
+            vec![
+                &page[..split - 5],
+                &page[split - 5..split + 5],
+                &page[split + 5..],
+            ],
+        ];
+
+        for chunks in &chunkings {
+            let dom = parse(chunks, false);
+            assert_eq!(body_text(&dom), "", "chunks {:?}", chunks);
+            assert!(is_past_head(&dom.document));
+        }
+        assert_eq!(body_text(&parse(&chunkings[1], true)), "HeadingFirstSecond");
+    }
+}
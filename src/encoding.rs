@@ -0,0 +1,296 @@
+use encoding_rs::{Decoder, Encoding, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252, X_USER_DEFINED};
+
+/// Number of bytes examined by the `<meta>` prescan, as in the HTML spec.
+const PRESCAN_LENGTH: usize = 1024;
+
+/// Detects the encoding of an HTML document from (in order) a byte order
+/// mark, the `charset` of the `Content-Type` header and a prescan of the
+/// first 1024 bytes for `<meta charset>` or `<meta http-equiv>`. Without any
+/// declaration, UTF-8 is assumed if `bytes` are valid UTF-8 and windows-1252
+/// otherwise. `complete` tells whether `bytes` are the whole document.
+fn detect(bytes: &[u8], content_type: Option<&str>, complete: bool) -> &'static Encoding {
+    Encoding::for_bom(bytes)
+        .map(|(encoding, _)| encoding)
+        .or_else(|| content_type.and_then(charset_from_content_type))
+        .or_else(|| prescan(&bytes[..bytes.len().min(PRESCAN_LENGTH)]))
+        .unwrap_or_else(|| match std::str::from_utf8(bytes) {
+            Ok(_) => UTF_8,
+            // A multi-byte sequence cut off at the end of a chunk.
+            Err(err) if err.error_len().is_none() && !complete => UTF_8,
+            Err(_) => WINDOWS_1252,
+        })
+}
+
+/// Decodes an HTML document as it arrives. The first 1024 bytes are held
+/// back until the encoding can be detected from them.
+pub struct HtmlDecoder {
+    content_type: Option<String>,
+    pending: Vec<u8>,
+    decoder: Option<Decoder>,
+}
+
+impl HtmlDecoder {
+    pub fn new(content_type: Option<&str>) -> Self {
+        HtmlDecoder {
+            content_type: content_type.map(str::to_string),
+            pending: Vec::new(),
+            decoder: None,
+        }
+    }
+
+    /// Decodes the next chunk of the document; `last` flushes what is left.
+    pub fn decode(&mut self, bytes: &[u8], last: bool) -> String {
+        let Some(decoder) = &mut self.decoder else {
+            self.pending.extend_from_slice(bytes);
+            if self.pending.len() < PRESCAN_LENGTH && !last {
+                return String::new();
+            }
+            let encoding = detect(&self.pending, self.content_type.as_deref(), last);
+            // The decoder still honours a BOM if present.
+            self.decoder = Some(encoding.new_decoder());
+            let pending = std::mem::take(&mut self.pending);
+            return self.decode(&pending, last);
+        };
+
+        let capacity = decoder
+            .max_utf8_buffer_length(bytes.len())
+            .unwrap_or(bytes.len() * 3);
+        let mut text = String::with_capacity(capacity);
+        let _ = decoder.decode_to_string(bytes, &mut text, last);
+        text
+    }
+
+    /// The encoding used, once the first chunk has been decoded.
+    pub fn encoding(&self) -> Option<&'static Encoding> {
+        self.decoder.as_ref().map(Decoder::encoding)
+    }
+}
+
+fn charset_from_content_type(content_type: &str) -> Option<&'static Encoding> {
+    content_type.split(';').skip(1).find_map(|parameter| {
+        let (name, value) = parameter.split_once('=')?;
+        if !name.trim().eq_ignore_ascii_case("charset") {
+            return None;
+        }
+        Encoding::for_label(
+            value
+                .trim()
+                .trim_matches(|c| c == '"' || c == '\'')
+                .as_bytes(),
+        )
+    })
+}
+
+/// Extracts the encoding from a `charset=` parameter inside the `content`
+/// of a `<meta http-equiv="content-type">`.
+fn charset_from_meta_content(content: &str) -> Option<&'static Encoding> {
+    let lower = content.to_ascii_lowercase();
+    let position = lower.find("charset")?;
+    let rest = content[position + "charset".len()..].trim_start();
+    let rest = rest.strip_prefix('=')?.trim_start();
+    let value = match rest.chars().next()? {
+        quote @ ('"' | '\'') => rest[1..].split(quote).next()?,
+        _ => rest
+            .split(|c: char| c.is_ascii_whitespace() || c == ';')
+            .next()?,
+    };
+    Encoding::for_label(value.as_bytes())
+}
+
+/// The "prescan a byte stream to determine its encoding" algorithm of the
+/// HTML spec, limited to what `<meta>` tags can declare.
+fn prescan(bytes: &[u8]) -> Option<&'static Encoding> {
+    let mut position = 0;
+    while position < bytes.len() {
+        let rest = &bytes[position..];
+        if rest.starts_with(b"<!--") {
+            position += find(rest, b"-->").map(|end| end + 3).unwrap_or(rest.len());
+        } else if rest.len() > 5
+            && rest[..5].eq_ignore_ascii_case(b"<meta")
+            && (rest[5].is_ascii_whitespace() || rest[5] == b'/')
+        {
+            let (attributes, length) = parse_attributes(&rest[5..]);
+            position += 5 + length;
+
+            let attribute = |name: &str| {
+                attributes
+                    .iter()
+                    .find(|(attribute, _)| attribute == name)
+                    .map(|(_, value)| value.as_str())
+            };
+            let encoding = match attribute("charset") {
+                Some(charset) => Encoding::for_label(charset.as_bytes()),
+                None if attribute("http-equiv")
+                    .is_some_and(|value| value.eq_ignore_ascii_case("content-type")) =>
+                {
+                    attribute("content").and_then(charset_from_meta_content)
+                }
+                None => None,
+            };
+            if let Some(encoding) = encoding {
+                // A document that was decoded well enough to read the
+                // declaration can't really be UTF-16.
+                return Some(if encoding == UTF_16BE || encoding == UTF_16LE {
+                    UTF_8
+                } else if encoding == X_USER_DEFINED {
+                    WINDOWS_1252
+                } else {
+                    encoding
+                });
+            }
+        } else if rest.len() > 1
+            && rest[0] == b'<'
+            && (rest[1].is_ascii_alphabetic() || rest[1] == b'/')
+        {
+            position += find(rest, b">").map(|end| end + 1).unwrap_or(rest.len());
+        } else if rest.starts_with(b"<!") || rest.starts_with(b"<?") {
+            position += find(rest, b">").map(|end| end + 1).unwrap_or(rest.len());
+        } else {
+            position += 1;
+        }
+    }
+    None
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// Parses the attributes of a tag up to its closing `>`, returning
+/// lowercased names with their values and the number of bytes consumed.
+fn parse_attributes(bytes: &[u8]) -> (Vec<(String, String)>, usize) {
+    let mut attributes = Vec::new();
+    let mut position = 0;
+
+    loop {
+        while position < bytes.len()
+            && (bytes[position].is_ascii_whitespace() || bytes[position] == b'/')
+        {
+            position += 1;
+        }
+        if position >= bytes.len() {
+            return (attributes, position);
+        }
+        if bytes[position] == b'>' {
+            return (attributes, position + 1);
+        }
+
+        let name_start = position;
+        while position < bytes.len()
+            && !bytes[position].is_ascii_whitespace()
+            && !matches!(bytes[position], b'=' | b'>' | b'/')
+        {
+            position += 1;
+        }
+        let name = String::from_utf8_lossy(&bytes[name_start..position]).to_ascii_lowercase();
+
+        while position < bytes.len() && bytes[position].is_ascii_whitespace() {
+            position += 1;
+        }
+        let mut value = String::new();
+        if position < bytes.len() && bytes[position] == b'=' {
+            position += 1;
+            while position < bytes.len() && bytes[position].is_ascii_whitespace() {
+                position += 1;
+            }
+            if position < bytes.len() && matches!(bytes[position], b'"' | b'\'') {
+                let quote = bytes[position];
+                let value_start = position + 1;
+                let length = bytes[value_start..]
+                    .iter()
+                    .position(|byte| *byte == quote)
+                    .unwrap_or(bytes.len() - value_start);
+                value = String::from_utf8_lossy(&bytes[value_start..value_start + length]).into();
+                position = (value_start + length + 1).min(bytes.len());
+            } else {
+                let value_start = position;
+                while position < bytes.len()
+                    && !bytes[position].is_ascii_whitespace()
+                    && bytes[position] != b'>'
+                {
+                    position += 1;
+                }
+                value = String::from_utf8_lossy(&bytes[value_start..position]).into();
+            }
+        }
+
+        if !name.is_empty() && !attributes.iter().any(|(existing, _)| *existing == name) {
+            attributes.push((name, value));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encoding_rs::{SHIFT_JIS, WINDOWS_1251};
+
+    /// Decodes `bytes` both in one go and split into small chunks.
+    fn decode_html(bytes: &[u8], content_type: Option<&str>) -> (String, &'static Encoding) {
+        let mut decoder = HtmlDecoder::new(content_type);
+        let text = decoder.decode(bytes, true);
+        let encoding = decoder.encoding().unwrap();
+
+        let mut decoder = HtmlDecoder::new(content_type);
+        let mut chunked = String::new();
+        for chunk in bytes.chunks(7) {
+            chunked.push_str(&decoder.decode(chunk, false));
+        }
+        chunked.push_str(&decoder.decode(&[], true));
+        assert_eq!(chunked, text);
+        assert_eq!(decoder.encoding(), Some(encoding));
+
+        (text, encoding)
+    }
+
+    #[test]
+    fn test_decode_html_bom() {
+        let (text, encoding) = decode_html(
+            b"\xEF\xBB\xBF<title>caf\xC3\xA9</title>",
+            Some("text/html; charset=windows-1251"),
+        );
+        assert_eq!(encoding, UTF_8);
+        assert_eq!(text, "<title>café</title>");
+    }
+
+    #[test]
+    fn test_decode_html_content_type() {
+        let (bytes, _, _) = WINDOWS_1251.encode("<title>Привет</title>");
+        let (text, encoding) = decode_html(&bytes, Some("text/html; charset=\"windows-1251\""));
+        assert_eq!(encoding, WINDOWS_1251);
+        assert_eq!(text, "<title>Привет</title>");
+    }
+
+    #[test]
+    fn test_decode_html_meta() {
+        let (bytes, _, _) = SHIFT_JIS.encode(
+            "<!-- <meta charset=\"utf-8\"> --><meta http-equiv='Content-Type' content='text/html; charset=Shift_JIS'><title>日本語</title>",
+        );
+        let (text, encoding) = decode_html(&bytes, Some("text/html"));
+        assert_eq!(encoding, SHIFT_JIS);
+        assert!(text.ends_with("<title>日本語</title>"));
+
+        assert_eq!(prescan(b"<meta charset=utf-16le>"), Some(UTF_8));
+        assert_eq!(prescan(b"<meta name=charset content=gbk>"), None);
+    }
+
+    #[test]
+    fn test_decode_html_fallback() {
+        assert_eq!(decode_html("café".as_bytes(), None).1, UTF_8);
+        assert_eq!(
+            decode_html(b"caf\xE9", None),
+            ("café".to_string(), WINDOWS_1252)
+        );
+
+        // A multi-byte character straddling the end of the prescan window.
+        let mut bytes = vec![b' '; PRESCAN_LENGTH - 1];
+        bytes.extend_from_slice("é".as_bytes());
+        let mut decoder = HtmlDecoder::new(None);
+        let mut text = decoder.decode(&bytes[..PRESCAN_LENGTH], false);
+        text.push_str(&decoder.decode(&bytes[PRESCAN_LENGTH..], true));
+        assert_eq!(decoder.encoding(), Some(UTF_8));
+        assert!(text.ends_with(" é"));
+    }
+}
@@ -1,7 +1,7 @@
-use std::io;
+use std::{io, sync::Arc};
 
 use axum::{
-    extract::rejection::PathRejection,
+    extract::rejection::{PathRejection, QueryRejection},
     http::StatusCode,
     response::{IntoResponse, Response},
     Json,
@@ -9,6 +9,8 @@ use axum::{
 use serde::Serialize;
 use thiserror::Error;
 
+use crate::{host_policy::HostNotAllowedError, ssrf::BlockedAddressError};
+
 #[derive(Debug, Error)]
 pub enum ServerError {
     #[error(transparent)]
@@ -18,13 +20,80 @@ pub enum ServerError {
     AxumPathRejection(#[from] PathRejection),
 
     #[error(transparent)]
-    ReqwestError(#[from] reqwest::Error),
+    AxumQueryRejection(#[from] QueryRejection),
+
+    #[error("URL is not valid percent-encoded UTF-8")]
+    InvalidUrlEncoding,
+
+    #[error(transparent)]
+    ReqwestError(reqwest::Error),
 
     #[error(transparent)]
     IOError(#[from] io::Error),
 
     #[error(transparent)]
     ParseURLError(#[from] url::ParseError),
+
+    #[error(transparent)]
+    XMLError(#[from] quick_xml::Error),
+
+    #[error(transparent)]
+    JSONError(#[from] serde_json::Error),
+
+    #[error(transparent)]
+    ForbiddenAddress(#[from] BlockedAddressError),
+
+    #[error(transparent)]
+    HostNotAllowed(#[from] HostNotAllowedError),
+
+    #[error("unsupported content type: {0}")]
+    UnsupportedContentType(String),
+
+    #[error("the page answered 304 Not Modified to an unconditional request")]
+    UnexpectedNotModified,
+
+    #[error("more than {0} redirects")]
+    TooManyRedirects(usize),
+
+    #[error("response body exceeds {0} bytes")]
+    BodyTooLarge(usize),
+
+    #[error("batch exceeds {0} URLs")]
+    BatchTooLarge(usize),
+
+    #[error("extraction took longer than {0} seconds")]
+    JobTimedOut(u64),
+
+    #[error("job not found: {0}")]
+    JobNotFound(String),
+
+    #[error("job queue is full")]
+    JobQueueFull,
+
+    #[error("callbacks require a webhook secret to be configured")]
+    WebhooksDisabled,
+
+    /// An error handed to every caller of a coalesced extraction.
+    #[error(transparent)]
+    Shared(Arc<ServerError>),
+}
+
+impl From<reqwest::Error> for ServerError {
+    /// Surfaces rejections by the address guard or the host policy, which
+    /// reach us wrapped in connect or redirect errors.
+    fn from(err: reqwest::Error) -> Self {
+        let mut source: Option<&(dyn std::error::Error + 'static)> = Some(&err);
+        while let Some(inner) = source {
+            if let Some(blocked) = inner.downcast_ref::<BlockedAddressError>() {
+                return ServerError::ForbiddenAddress(blocked.clone());
+            }
+            if let Some(denied) = inner.downcast_ref::<HostNotAllowedError>() {
+                return ServerError::HostNotAllowed(denied.clone());
+            }
+            source = inner.source();
+        }
+        ServerError::ReqwestError(err)
+    }
 }
 
 #[derive(Serialize)]
@@ -32,9 +101,9 @@ struct ErrorResponse {
     error: String,
 }
 
-impl IntoResponse for ServerError {
-    fn into_response(self) -> Response {
-        let (status, error_message) = match self {
+impl ServerError {
+    pub fn status_and_message(&self) -> (StatusCode, String) {
+        match self {
             ServerError::ValidationError(err) => {
                 let error_message = err
                     .field_errors()
@@ -54,10 +123,34 @@ impl IntoResponse for ServerError {
                 )
             }
             ServerError::AxumPathRejection(_) => (StatusCode::BAD_REQUEST, self.to_string()),
+            ServerError::AxumQueryRejection(_) => (StatusCode::BAD_REQUEST, self.to_string()),
+            ServerError::InvalidUrlEncoding => (StatusCode::BAD_REQUEST, self.to_string()),
             ServerError::ReqwestError(_) => (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()),
             ServerError::IOError(_) => (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()),
             Self::ParseURLError(_) => (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()),
-        };
+            Self::XMLError(_) => (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()),
+            Self::JSONError(_) => (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()),
+            Self::ForbiddenAddress(_) => (StatusCode::FORBIDDEN, self.to_string()),
+            Self::HostNotAllowed(_) => (StatusCode::FORBIDDEN, self.to_string()),
+            Self::UnsupportedContentType(_) => {
+                (StatusCode::UNSUPPORTED_MEDIA_TYPE, self.to_string())
+            }
+            Self::UnexpectedNotModified => (StatusCode::BAD_GATEWAY, self.to_string()),
+            Self::TooManyRedirects(_) => (StatusCode::BAD_GATEWAY, self.to_string()),
+            Self::BodyTooLarge(_) => (StatusCode::PAYLOAD_TOO_LARGE, self.to_string()),
+            Self::BatchTooLarge(_) => (StatusCode::PAYLOAD_TOO_LARGE, self.to_string()),
+            Self::JobTimedOut(_) => (StatusCode::GATEWAY_TIMEOUT, self.to_string()),
+            Self::JobNotFound(_) => (StatusCode::NOT_FOUND, self.to_string()),
+            Self::JobQueueFull => (StatusCode::SERVICE_UNAVAILABLE, self.to_string()),
+            Self::WebhooksDisabled => (StatusCode::BAD_REQUEST, self.to_string()),
+            Self::Shared(err) => err.status_and_message(),
+        }
+    }
+}
+
+impl IntoResponse for ServerError {
+    fn into_response(self) -> Response {
+        let (status, error_message) = self.status_and_message();
         let body = Json(ErrorResponse {
             error: error_message,
         });
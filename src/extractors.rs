@@ -1,8 +1,8 @@
 use crate::errors::ServerError;
 use axum::async_trait;
-use axum::extract::rejection::PathRejection;
+use axum::extract::rejection::{PathRejection, QueryRejection};
 use axum::extract::FromRequestParts;
-use axum::extract::Path;
+use axum::extract::{Path, Query};
 use axum::http::request::Parts;
 use serde::de::DeserializeOwned;
 use validator::Validate;
@@ -25,3 +25,22 @@ where
         Ok(ValidatedPath(value))
     }
 }
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ValidatedQuery<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for ValidatedQuery<T>
+where
+    T: DeserializeOwned + Validate,
+    S: Send + Sync,
+    Query<T>: FromRequestParts<S, Rejection = QueryRejection>,
+{
+    type Rejection = ServerError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Query(value) = Query::<T>::from_request_parts(parts, state).await?;
+        value.validate()?;
+        Ok(ValidatedQuery(value))
+    }
+}
@@ -0,0 +1,202 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+
+/// Short names accepted for the first segment of a field path.
+const ALIASES: [(&str, &str); 2] = [("og", "og_tags"), ("twitter", "twitter_tags")];
+
+/// Fields always kept in a filtered response.
+const ALWAYS_INCLUDED: [&str; 1] = ["warnings"];
+
+/// A selection of `PageInfo` fields, parsed from a comma-separated list of
+/// dot-separated paths such as `title,og.image,manifest.icons`.
+///
+/// A path selects the whole value it points to. Paths through an array
+/// apply to each of its items.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Fields {
+    tree: FieldTree,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+struct FieldTree {
+    /// The whole value is selected.
+    selected: bool,
+    children: BTreeMap<String, FieldTree>,
+}
+
+impl Fields {
+    pub fn parse(fields: &str) -> Self {
+        let mut tree = FieldTree::default();
+        for field in fields.split(',').map(str::trim).filter(|f| !f.is_empty()) {
+            let mut segments: Vec<&str> = field.split('.').collect();
+            if let Some((_, name)) = ALIASES.iter().find(|(alias, _)| *alias == segments[0]) {
+                segments[0] = name;
+            }
+            // `manifest` is the manifest URL; its members live in `manifest_info`.
+            if segments[0] == "manifest" && segments.len() > 1 {
+                segments[0] = "manifest_info";
+            }
+
+            let mut node = &mut tree;
+            for segment in segments {
+                node = node.children.entry(segment.to_string()).or_default();
+                if node.selected {
+                    break;
+                }
+            }
+            // Selecting a value selects all of its parts.
+            node.selected = true;
+            node.children.clear();
+        }
+        for field in ALWAYS_INCLUDED {
+            let node = tree.children.entry(field.to_string()).or_default();
+            node.selected = true;
+            node.children.clear();
+        }
+        Fields { tree }
+    }
+
+    /// Whether any part of the top-level `field` is selected.
+    pub fn includes(&self, field: &str) -> bool {
+        self.tree.children.contains_key(field)
+    }
+
+    /// Keeps only the selected parts of a serialized `PageInfo`.
+    pub fn filter(&self, value: Value) -> Value {
+        self.tree.filter(value).unwrap_or(Value::Object(Map::new()))
+    }
+
+    fn paths(&self) -> Vec<String> {
+        let mut paths = Vec::new();
+        self.tree.collect_paths("", &mut paths);
+        paths.retain(|path| !ALWAYS_INCLUDED.contains(&path.as_str()));
+        paths
+    }
+}
+
+impl FieldTree {
+    fn filter(&self, value: Value) -> Option<Value> {
+        if self.selected {
+            return Some(value);
+        }
+        match value {
+            Value::Object(object) => Some(Value::Object(
+                object
+                    .into_iter()
+                    .filter_map(|(key, value)| {
+                        let filtered = self.children.get(&key)?.filter(value)?;
+                        Some((key, filtered))
+                    })
+                    .collect(),
+            )),
+            Value::Array(items) => Some(Value::Array(
+                items
+                    .into_iter()
+                    .filter_map(|item| self.filter(item))
+                    .collect(),
+            )),
+            _ => None,
+        }
+    }
+
+    fn collect_paths(&self, prefix: &str, paths: &mut Vec<String>) {
+        if self.selected {
+            paths.push(prefix.to_string());
+            return;
+        }
+        for (name, child) in &self.children {
+            let path = if prefix.is_empty() {
+                name.clone()
+            } else {
+                format!("{}.{}", prefix, name)
+            };
+            child.collect_paths(&path, paths);
+        }
+    }
+}
+
+/// Serialized in a normalized form, so that equivalent selections share
+/// cache entries.
+impl Serialize for Fields {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.paths().join(","))
+    }
+}
+
+impl<'de> Deserialize<'de> for Fields {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Fields::parse(&String::deserialize(deserializer)?))
+    }
+}
+
+/// Deserializes an optional selection. A list without any path, such as
+/// `fields=`, selects everything rather than only the `warnings`.
+pub fn deserialize_optional<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Fields>, D::Error> {
+    let fields = Option::<String>::deserialize(deserializer)?;
+    Ok(fields
+        .filter(|fields| fields.split(',').any(|field| !field.trim().is_empty()))
+        .map(|fields| Fields::parse(&fields)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_fields_filter() {
+        let page_info = json!({
+            "title": "Title",
+            "description": "Description",
+            "og_tags": {
+                "title": "OG title",
+                "image": [{ "url": "https://example.com/a.png", "width": "100" }]
+            },
+            "manifest": "https://example.com/manifest.json",
+            "manifest_info": {
+                "name": "App",
+                "icons": [{ "src": "https://example.com/icon.png" }]
+            },
+            "icons": [
+                { "href": "https://example.com/a.ico", "rel": "icon" },
+                { "href": "https://example.com/b.png", "rel": "apple-touch-icon" }
+            ],
+            "warnings": [{ "resource": "oembed" }]
+        });
+
+        let fields = Fields::parse("title, og.image.url,manifest.icons,icons.href,missing.field");
+        assert_eq!(
+            fields.filter(page_info),
+            json!({
+                "title": "Title",
+                "og_tags": { "image": [{ "url": "https://example.com/a.png" }] },
+                "manifest_info": { "icons": [{ "src": "https://example.com/icon.png" }] },
+                "icons": [
+                    { "href": "https://example.com/a.ico" },
+                    { "href": "https://example.com/b.png" }
+                ],
+                "warnings": [{ "resource": "oembed" }]
+            })
+        );
+
+        assert!(fields.includes("manifest_info"));
+        assert!(!fields.includes("manifest"));
+        assert!(!fields.includes("oembed"));
+    }
+
+    #[test]
+    fn test_fields_serialize() {
+        let fields = Fields::parse("og.image,title,og_tags.image,og");
+        assert_eq!(
+            serde_json::to_value(&fields).unwrap(),
+            json!("og_tags,title")
+        );
+        assert_eq!(
+            serde_json::from_value::<Fields>(json!("title,og")).unwrap(),
+            fields
+        );
+    }
+}
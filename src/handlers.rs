@@ -1,18 +1,42 @@
 use axum::{
-    http::StatusCode,
+    body::Bytes,
+    extract::{Path, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
     response::{IntoResponse, Response, Result},
     Json,
 };
-use html5ever::parse_document;
-use html5ever::tendril::TendrilSink;
 use markup5ever_rcdom::{Handle, NodeData, RcDom};
 use percent_encoding::percent_decode_str;
+use reqwest::Method;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use sha2::{Digest, Sha256};
+use std::{collections::HashMap, sync::Arc};
+use tokio::task::JoinSet;
 use url::Url;
 use validator::{Validate, ValidationError};
 
-use crate::{errors::ServerError, extractors::ValidatedPath};
+use crate::{
+    cache::{self, CachedPage, Freshness, Lookup, Validators},
+    document,
+    errors::ServerError,
+    extractors::{ValidatedPath, ValidatedQuery},
+    fields::{self, Fields},
+    icons::{self, Icon},
+    jobs::Job,
+    json_ld,
+    links::{self, AlternateLanguage, Feed, Refresh},
+    manifest::{self, ManifestInfo},
+    microdata,
+    oembed::{self, OEmbed, OEmbedFormat, OEmbedLink},
+    preview::{self, DocumentHints, Preview},
+    state::{AppState, BatchLimiter, Redirect},
+};
+
+const DEFAULT_ICON_SIZE: u32 = 32;
+
+/// Tells whether a response was served from the cache: `HIT`, `STALE`
+/// (served while being refreshed) or `MISS`.
+const X_CACHE: &str = "x-cache";
 
 #[derive(Debug, Deserialize, Validate)]
 pub struct UrlPath {
@@ -26,13 +50,74 @@ pub struct UrlPath {
     url: String,
 }
 
+#[derive(Debug, Deserialize, Validate)]
+pub struct UrlQuery {
+    #[validate(
+        url(message = "Invalid URL"),
+        custom(
+            function = "validate_schema",
+            message = "URL must start with http:// or https://"
+        )
+    )]
+    url: String,
+}
+
 #[derive(Debug, Serialize, Default, Deserialize)]
 pub struct PageInfo {
+    /// The URL the page was served from, after redirects.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    final_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    redirects: Option<Vec<Redirect>>,
+    /// The `<link rel="canonical">` URL declared by the page.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    canonical_url: Option<String>,
+    /// The AMP version of the page.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    amphtml: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    prev: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    next: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    alternate_languages: Option<Vec<AlternateLanguage>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    feeds: Option<Vec<Feed>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    refresh: Option<Refresh>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    encoding: Option<String>,
+    /// The `lang` and `dir` of `<html>`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    lang: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dir: Option<String>,
+    /// A summary for link previews, resolved from all other sources.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    preview: Option<Preview>,
     #[serde(skip_serializing_if = "Option::is_none")]
     title: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     description: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    keywords: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    author: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    robots: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    generator: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    theme_color: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    color_scheme: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    viewport: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    application_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    referrer: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     favicon: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     name: Option<String>,
@@ -41,7 +126,88 @@ pub struct PageInfo {
     #[serde(skip_serializing_if = "Option::is_none")]
     manifest: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    manifest_info: Option<ManifestInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    icons: Option<Vec<Icon>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    best_icon: Option<Icon>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     og_tags: Option<HashMap<String, serde_json::Value>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    twitter_tags: Option<HashMap<String, serde_json::Value>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    oembed: Option<OEmbed>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    json_ld: Option<Vec<serde_json::Value>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    microdata: Option<Vec<serde_json::Value>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rdfa: Option<Vec<serde_json::Value>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    original_urls: Option<HashMap<String, String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    warnings: Option<Vec<Warning>>,
+}
+
+impl PageInfo {
+    fn add_warning(&mut self, resource: &str, url: &str, err: ServerError) {
+        tracing::warn!("failed to fetch {} {}: {}", resource, url, err);
+        self.warnings.get_or_insert_with(Vec::new).push(Warning {
+            resource: resource.to_string(),
+            url: url.to_string(),
+            message: err.to_string(),
+        });
+    }
+}
+
+/// What the document says beyond `PageInfo`, only used while building it.
+#[derive(Debug, Default)]
+struct ParseExtras {
+    oembed_links: Vec<OEmbedLink>,
+    hints: DocumentHints,
+}
+
+/// A sub-resource that could not be fetched. The page itself was still
+/// extracted, only the data from this resource is missing.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Warning {
+    resource: String,
+    url: String,
+    message: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Validate)]
+pub struct ExtractOptions {
+    /// Report the raw value of every resolved URL field in `original_urls`.
+    #[serde(default)]
+    original_urls: bool,
+    /// Read the whole document instead of stopping after `<head>`, for
+    /// JSON-LD, microdata and RDFa in the body. The preview only falls back
+    /// to the first `<h1>` and the largest `<img>` with it.
+    #[serde(default)]
+    body: bool,
+    /// Target size in pixels used to pick `best_icon`.
+    icon_size: Option<u32>,
+    /// Passed through to the oEmbed provider.
+    maxwidth: Option<u32>,
+    /// Passed through to the oEmbed provider.
+    maxheight: Option<u32>,
+    /// Follow at most this many redirects for the page, 0 to follow none.
+    /// The configured maximum still applies.
+    max_redirects: Option<usize>,
+    /// Only return these fields, and skip sub-requests that none of them
+    /// needs.
+    #[serde(default, deserialize_with = "fields::deserialize_optional")]
+    fields: Option<Fields>,
+}
+
+impl ExtractOptions {
+    /// Whether the top-level `field` should be extracted.
+    fn wants(&self, field: &str) -> bool {
+        self.fields
+            .as_ref()
+            .is_none_or(|fields| fields.includes(field))
+    }
 }
 
 fn validate_schema(url: &str) -> Result<(), ValidationError> {
@@ -55,102 +221,715 @@ pub async fn handle_health() -> impl IntoResponse {
     StatusCode::OK
 }
 pub async fn handle_extract(
+    State(state): State<Arc<AppState>>,
     ValidatedPath(url): ValidatedPath<UrlPath>,
+    ValidatedQuery(options): ValidatedQuery<ExtractOptions>,
+    headers: HeaderMap,
 ) -> Result<Response, ServerError> {
     // Validate URL
     url.validate()?;
 
     let decoded_url = percent_decode_str(&url.url)
         .decode_utf8()
-        .unwrap()
+        .map_err(|_| ServerError::InvalidUrlEncoding)?
         .to_string();
 
-    match fetch_html(&decoded_url).await {
-        Ok((status, html)) => {
-            if status.is_success() {
-                let mut page_info = extract_info(&html).await?;
-                if let Some(manifest) = &mut page_info.manifest {
-                    let base_url = trim_url(&decoded_url)?;
-                    *manifest = format!("{}{}", base_url, manifest);
-
-                    let json = fetch_json(manifest).await?;
-
-                    page_info.short_name = json
-                        .get("short_name")
-                        .and_then(|value| value.as_str())
-                        .map(|value| value.to_string());
-                    page_info.name = json
-                        .get("name")
-                        .and_then(|value| value.as_str())
-                        .map(|value| value.to_string());
-                }
-                Ok(Json(page_info).into_response())
+    let (extraction, cache_status) = extract_cached(&state, decoded_url, options, None).await?;
+    Ok(respond(extraction, &headers, cache_status))
+}
+
+/// `GET /extract?url=…`: the same extraction as [`handle_extract`], with the
+/// URL passed as a regular query parameter next to the options.
+pub async fn handle_extract_query(
+    State(state): State<Arc<AppState>>,
+    ValidatedQuery(query): ValidatedQuery<UrlQuery>,
+    ValidatedQuery(options): ValidatedQuery<ExtractOptions>,
+    headers: HeaderMap,
+) -> Result<Response, ServerError> {
+    let (extraction, cache_status) = extract_cached(&state, query.url, options, None).await?;
+    Ok(respond(extraction, &headers, cache_status))
+}
+
+/// An entry of a batch: a URL, or an object with the URL and its extraction
+/// options.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum BatchItem {
+    Url(String),
+    WithOptions {
+        url: String,
+        #[serde(flatten)]
+        options: ExtractOptions,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BatchResult {
+    pub url: String,
+    /// The status `GET /*url` would have answered with.
+    pub status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cache: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<serde_json::Value>,
+    /// `code` and `message` of a failed extraction.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<serde_json::Value>,
+}
+
+pub async fn handle_batch(
+    State(state): State<Arc<AppState>>,
+    Json(items): Json<Vec<BatchItem>>,
+) -> Result<Json<Vec<BatchResult>>, ServerError> {
+    if items.len() > state.batch.max_size() {
+        return Err(ServerError::BatchTooLarge(state.batch.max_size()));
+    }
+
+    // Dropping the set, as happens when the client goes away, aborts every
+    // extraction of the batch.
+    let mut tasks = JoinSet::new();
+    let count = items.len();
+    for (index, item) in items.into_iter().enumerate() {
+        let (url, options) = match item {
+            BatchItem::Url(url) => (url, ExtractOptions::default()),
+            BatchItem::WithOptions { url, options } => (url, options),
+        };
+        let state = state.clone();
+        tasks.spawn(async move {
+            let result = batch_extract(&state, url, options, Some(&state.batch)).await;
+            (index, result)
+        });
+    }
+
+    let mut results: Vec<Option<BatchResult>> = (0..count).map(|_| None).collect();
+    while let Some(task) = tasks.join_next().await {
+        let (index, result) =
+            task.unwrap_or_else(|err| std::panic::resume_unwind(err.into_panic()));
+        results[index] = Some(result);
+    }
+    Ok(Json(results.into_iter().flatten().collect()))
+}
+
+/// Extracts one URL of a batch or a job, reporting failures in the result.
+/// Fetches that miss the cache wait for a slot of `limiter`, if given.
+pub async fn batch_extract(
+    state: &Arc<AppState>,
+    url: String,
+    options: ExtractOptions,
+    limiter: Option<&BatchLimiter>,
+) -> BatchResult {
+    let extraction = match (UrlPath { url: url.clone() }).validate() {
+        Ok(()) => extract_cached(state, url.clone(), options, limiter).await,
+        Err(err) => Err(err.into()),
+    };
+    let mut result = BatchResult {
+        url,
+        status: StatusCode::OK.as_u16(),
+        cache: None,
+        result: None,
+        error: None,
+    };
+    match extraction {
+        Ok((extraction, cache_status)) => {
+            let mut body: serde_json::Value =
+                serde_json::from_slice(&extraction.body).unwrap_or_default();
+            result.status = extraction.status.as_u16();
+            result.cache = Some(cache_status.to_string());
+            if extraction.status == StatusCode::OK {
+                result.result = Some(body);
             } else {
-                let body = Json(serde_json::json!({
-                    "error": {
-                        "code": status.as_u16(),
-                        "message": status.canonical_reason().unwrap_or("Unknown error")
+                result.error = Some(body["error"].take());
+            }
+        }
+        Err(err) => return BatchResult::failed(result.url, &err),
+    }
+    result
+}
+
+impl BatchResult {
+    pub fn failed(url: String, err: &ServerError) -> Self {
+        let (status, message) = err.status_and_message();
+        BatchResult {
+            url,
+            status: status.as_u16(),
+            cache: None,
+            result: None,
+            error: Some(serde_json::json!({
+                "code": status.as_u16(),
+                "message": message
+            })),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct JobRequest {
+    #[validate(
+        url(message = "Invalid URL"),
+        custom(
+            function = "validate_schema",
+            message = "URL must start with http:// or https://"
+        )
+    )]
+    url: String,
+    /// Receives the finished job, signed with the webhook secret.
+    #[validate(
+        url(message = "Invalid callback URL"),
+        custom(
+            function = "validate_schema",
+            message = "Callback URL must start with http:// or https://"
+        )
+    )]
+    callback_url: Option<String>,
+    #[serde(flatten)]
+    options: ExtractOptions,
+}
+
+pub async fn handle_create_job(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<JobRequest>,
+) -> Result<Response, ServerError> {
+    request.validate()?;
+    if request.callback_url.is_some() && state.jobs.webhook_secret().is_none() {
+        return Err(ServerError::WebhooksDisabled);
+    }
+
+    let job = state
+        .jobs
+        .submit(request.url, request.options, request.callback_url)?;
+    let location = format!("/jobs/{}", job.id);
+    Ok((
+        StatusCode::ACCEPTED,
+        [(header::LOCATION, location)],
+        Json(job),
+    )
+        .into_response())
+}
+
+pub async fn handle_get_job(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Json<Job>, ServerError> {
+    state
+        .jobs
+        .get(&id)
+        .map(Json)
+        .ok_or(ServerError::JobNotFound(id))
+}
+
+/// Serves an extraction from the cache, refreshing stale results in the
+/// background, or extracts the page. Returns the `X-Cache` status with it.
+async fn extract_cached(
+    state: &Arc<AppState>,
+    decoded_url: String,
+    options: ExtractOptions,
+    limiter: Option<&BatchLimiter>,
+) -> Result<(Extraction, &'static str), ServerError> {
+    // Cached results of hosts denied since are not served.
+    let url = Url::parse(&decoded_url)?;
+    state.check_url(&url)?;
+    let key = cache::key(&decoded_url, &options);
+    match state.cache.get(&key).await {
+        Some(Lookup::Fresh(body)) => return Ok((Extraction::ok(body), "HIT")),
+        Some(Lookup::Stale(page)) => {
+            // Concurrent refreshes of the same entry share one extraction.
+            let body = page.body.clone();
+            let state = state.clone();
+            tokio::spawn(async move {
+                let result = extract_once(&state, &decoded_url, &options, &key, Some(&page)).await;
+                if let Err(err) = result {
+                    tracing::warn!("failed to refresh {}: {}", decoded_url, err);
+                }
+            });
+            return Ok((Extraction::ok(body), "STALE"));
+        }
+        None => {}
+    }
+
+    // Only fetches are limited, cache hits are served right away.
+    let _permits = match limiter {
+        Some(limiter) => Some(limiter.acquire(url.host_str().unwrap_or_default()).await),
+        None => None,
+    };
+    let extraction = extract_once(state, &decoded_url, &options, &key, None).await?;
+    Ok((extraction, "MISS"))
+}
+
+/// The JSON response of an extraction, shared by every request waiting for
+/// it.
+#[derive(Debug, Clone)]
+pub struct Extraction {
+    status: StatusCode,
+    body: Bytes,
+}
+
+impl Extraction {
+    fn ok(body: Bytes) -> Self {
+        Extraction {
+            status: StatusCode::OK,
+            body,
+        }
+    }
+}
+
+impl IntoResponse for Extraction {
+    fn into_response(self) -> Response {
+        (self.status, json_response(self.body)).into_response()
+    }
+}
+
+/// Runs [`extract`], unless an extraction with the same key is already under
+/// way, in which case its result is awaited instead.
+async fn extract_once(
+    state: &AppState,
+    decoded_url: &str,
+    options: &ExtractOptions,
+    key: &str,
+    cached: Option<&CachedPage>,
+) -> Result<Extraction, ServerError> {
+    state
+        .extractions
+        .run(key, || async {
+            extract(state, decoded_url, options, key, cached)
+                .await
+                .map_err(Arc::new)
+        })
+        .await
+        .map_err(|err| Arc::try_unwrap(err).unwrap_or_else(ServerError::Shared))
+}
+
+/// Fetches and extracts `url`, caching successful results under `key`. With
+/// a `cached` result, the page is revalidated and that result is kept if the
+/// page has not changed.
+async fn extract(
+    state: &AppState,
+    decoded_url: &str,
+    options: &ExtractOptions,
+    key: &str,
+    cached: Option<&CachedPage>,
+) -> Result<Extraction, ServerError> {
+    let validators = cached.map(|page| &page.validators);
+    match (
+        fetch_html(state, decoded_url, options, validators).await,
+        cached,
+    ) {
+        (
+            Ok(FetchedPage {
+                status: StatusCode::NOT_MODIFIED,
+                freshness,
+                validators,
+                ..
+            }),
+            Some(cached),
+        ) => {
+            let validators = validators.or(&cached.validators);
+            state
+                .cache
+                .insert(key, cached.body.clone(), freshness, validators)
+                .await;
+            Ok(Extraction::ok(cached.body.clone()))
+        }
+        // Without a cached result, nothing was asked to be revalidated.
+        (
+            Ok(FetchedPage {
+                status: StatusCode::NOT_MODIFIED,
+                ..
+            }),
+            None,
+        ) => Err(ServerError::UnexpectedNotModified),
+        (
+            Ok(FetchedPage {
+                final_url,
+                redirects,
+                freshness,
+                validators,
+                page_info: Some((mut page_info, extras)),
+                ..
+            }),
+            _,
+        ) => {
+            page_info.final_url = Some(final_url.to_string());
+            if !redirects.is_empty() {
+                page_info.redirects = Some(redirects);
+            }
+            // Sub-resources are only fetched for the fields they contribute to.
+            let wants_icons = options.wants("icons") || options.wants("best_icon");
+            let wants_preview = options.wants("preview");
+            let wants_manifest = wants_icons
+                || wants_preview
+                || options.wants("manifest_info")
+                || options.wants("name")
+                || options.wants("short_name");
+            if let Some(manifest) = page_info.manifest.clone().filter(|_| wants_manifest) {
+                match fetch_manifest(state, &manifest, &final_url).await {
+                    Ok(manifest_info) => {
+                        let manifest_icons = icons::from_manifest(&manifest_info);
+                        if !manifest_icons.is_empty() {
+                            page_info
+                                .icons
+                                .get_or_insert_with(Vec::new)
+                                .extend(manifest_icons);
+                        }
+                        page_info.short_name = manifest_info.short_name.clone();
+                        page_info.name = manifest_info.name.clone();
+                        page_info.manifest_info = Some(manifest_info);
                     }
-                }));
-                Ok((status, body).into_response())
+                    Err(err) => page_info.add_warning("manifest", &manifest, err),
+                }
+            }
+
+            let oembed_link = extras
+                .oembed_links
+                .iter()
+                .find(|link| link.format == OEmbedFormat::Json)
+                .or_else(|| extras.oembed_links.first())
+                .cloned()
+                .or_else(|| state.oembed_providers.find(final_url.as_str()))
+                .or_else(|| state.oembed_providers.find(decoded_url));
+            if let Some(link) = oembed_link.filter(|_| options.wants("oembed") || wants_preview) {
+                match fetch_oembed(state, &link, options).await {
+                    Ok(oembed) => page_info.oembed = Some(oembed),
+                    Err(err) => page_info.add_warning("oembed", &link.href, err),
+                }
+            }
+
+            // The preview takes nothing from icons, so it needs no probe.
+            if page_info.icons.is_none() && wants_icons {
+                let favicon_url = final_url.join("/favicon.ico")?;
+                match probe_favicon(state, &favicon_url).await {
+                    Ok(favicon) => page_info.icons = favicon.map(|favicon| vec![favicon]),
+                    Err(err) => page_info.add_warning("favicon", favicon_url.as_str(), err),
+                }
+            }
+            page_info.best_icon = page_info.icons.as_deref().and_then(|icons| {
+                icons::best_icon(icons, options.icon_size.unwrap_or(DEFAULT_ICON_SIZE)).cloned()
+            });
+            if wants_preview {
+                page_info.preview = Some(build_preview(&page_info, &extras.hints));
+            }
+
+            let body = page_body(&page_info, options)?;
+            state
+                .cache
+                .insert(key, body.clone(), freshness, validators)
+                .await;
+            Ok(Extraction::ok(body))
+        }
+        // The limit was reached: report where the page leads instead.
+        (
+            Ok(FetchedPage {
+                final_url,
+                redirects,
+                page_info: None,
+                ..
+            }),
+            _,
+        ) if redirects
+            .last()
+            .is_some_and(|redirect| redirect.url == final_url.as_str()) =>
+        {
+            let location = redirects.last().map(|redirect| redirect.location.clone());
+            let page_info = PageInfo {
+                final_url: Some(final_url.to_string()),
+                warnings: Some(vec![Warning {
+                    resource: "redirect".to_string(),
+                    url: location.unwrap_or_default(),
+                    message: format!("not followed after {} redirects", redirects.len() - 1),
+                }]),
+                redirects: Some(redirects),
+                ..Default::default()
+            };
+            Ok(Extraction::ok(page_body(&page_info, options)?))
+        }
+        (
+            Ok(FetchedPage {
+                status,
+                final_url,
+                redirects,
+                page_info: None,
+                ..
+            }),
+            _,
+        ) => {
+            // A redirect without a usable `Location` can't be passed on.
+            let status = if status.is_redirection() {
+                StatusCode::BAD_GATEWAY
+            } else {
+                status
+            };
+            let mut body = serde_json::json!({
+                "error": {
+                    "code": status.as_u16(),
+                    "message": status.canonical_reason().unwrap_or("Unknown error")
+                }
+            });
+            if !redirects.is_empty() {
+                body["final_url"] = final_url.to_string().into();
+                body["redirects"] = serde_json::to_value(redirects).unwrap_or_default();
             }
+            Ok(Extraction {
+                status,
+                body: Bytes::from(serde_json::to_vec(&body)?),
+            })
         }
-        Err(err) => {
+        (Err(ServerError::ReqwestError(err)), _) => {
             let status = err.status().unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
-            let body = Json(serde_json::json!({
+            let body = serde_json::json!({
                 "error": {
                     "code": status.as_u16(),
                     "message": err.to_string()
                 }
-            }));
-            Ok((status, body).into_response())
+            });
+            Ok(Extraction {
+                status,
+                body: Bytes::from(serde_json::to_vec(&body)?),
+            })
         }
+        (Err(err), _) => Err(err),
     }
 }
 
-fn trim_url(url: &str) -> Result<String, url::ParseError> {
-    let url = Url::parse(url)?;
-    let host = url.host_str().unwrap_or("");
-    let scheme = url.scheme();
-    let port = url.port().map(|p| format!(":{}", p)).unwrap_or_default();
-    Ok(format!("{}://{}{}", scheme, host, port))
+/// Serializes `page_info`, keeping only the requested fields.
+fn page_body(page_info: &PageInfo, options: &ExtractOptions) -> Result<Bytes, ServerError> {
+    let body = match &options.fields {
+        Some(fields) => serde_json::to_vec(&fields.filter(serde_json::to_value(page_info)?))?,
+        None => serde_json::to_vec(page_info)?,
+    };
+    Ok(Bytes::from(body))
+}
+
+fn json_response(body: Bytes) -> Response {
+    ([(header::CONTENT_TYPE, "application/json")], body).into_response()
+}
+
+/// Builds the response to a client, tagging successful extractions with an
+/// `ETag` of their body and answering `304 Not Modified` when the request's
+/// `If-None-Match` already names it.
+fn respond(extraction: Extraction, headers: &HeaderMap, cache_status: &'static str) -> Response {
+    let mut response = if extraction.status == StatusCode::OK {
+        let etag = etag(&extraction.body);
+        let not_modified = headers
+            .get(header::IF_NONE_MATCH)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| etag_matches(value, &etag));
+        if not_modified {
+            (StatusCode::NOT_MODIFIED, [(header::ETAG, etag)]).into_response()
+        } else {
+            ([(header::ETAG, etag)], extraction).into_response()
+        }
+    } else {
+        extraction.into_response()
+    };
+    response
+        .headers_mut()
+        .insert(X_CACHE, HeaderValue::from_static(cache_status));
+    response
 }
 
-async fn fetch_html(url: &str) -> Result<(StatusCode, String), reqwest::Error> {
-    let response = reqwest::get(url).await?;
-    let status = response.status();
-    let text = response.text().await?;
-    Ok((status, text))
+fn etag(body: &[u8]) -> String {
+    format!("\"{:x}\"", Sha256::digest(body))
 }
 
-async fn fetch_json(url: &str) -> Result<serde_json::Value, ServerError> {
-    let body: serde_json::Value = reqwest::get(url).await?.json().await?;
+/// Compares `etag` against an `If-None-Match` list, weakly as RFC 9110
+/// requires.
+fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+    if_none_match.trim() == "*"
+        || if_none_match
+            .split(',')
+            .any(|tag| tag.trim().trim_start_matches("W/") == etag)
+}
 
-    Ok(body)
+/// The page response, with the extracted data when it was successful.
+struct FetchedPage {
+    status: StatusCode,
+    final_url: Url,
+    redirects: Vec<Redirect>,
+    freshness: Freshness,
+    validators: Validators,
+    page_info: Option<(PageInfo, ParseExtras)>,
+}
+
+/// Fetches and extracts the page, conditionally when `validators` of a
+/// previous response are given. Non-success responses, including a redirect
+/// that was not followed and `304 Not Modified`, are returned without reading
+/// their body.
+async fn fetch_html(
+    state: &AppState,
+    url: &str,
+    options: &ExtractOptions,
+    validators: Option<&Validators>,
+) -> Result<FetchedPage, ServerError> {
+    let headers = validators
+        .map(Validators::conditional_headers)
+        .unwrap_or_default();
+    let (response, redirects) = state
+        .follow(Method::GET, url, headers, options.max_redirects)
+        .await?;
+    let mut page = FetchedPage {
+        status: response.status(),
+        final_url: response.url().clone(),
+        redirects,
+        freshness: Freshness::from_headers(response.headers()),
+        validators: Validators::from_headers(response.headers()),
+        page_info: None,
+    };
+    if !page.status.is_success() {
+        return Ok(page);
+    }
+
+    let (document_url, options) = (page.final_url.clone(), options.clone());
+    let ((mut page_info, extras), encoding) =
+        document::parse_response(response, state.max_body_size, options.body, move |dom| {
+            extract_info(dom, &document_url, &options)
+        })
+        .await?;
+    page_info.encoding = Some(encoding.name().to_string());
+    page.page_info = Some((page_info, extras));
+    Ok(page)
+}
+
+/// Checks whether the origin serves `/favicon.ico` for pages that don't
+/// declare any icon. A missing favicon is the common case, not a failure.
+async fn probe_favicon(state: &AppState, favicon_url: &Url) -> Result<Option<Icon>, ServerError> {
+    let response = state.send(Method::HEAD, favicon_url.as_str()).await?;
+    if matches!(response.status(), StatusCode::NOT_FOUND | StatusCode::GONE) {
+        return Ok(None);
+    }
+    response.error_for_status()?;
+    Ok(Some(Icon {
+        href: favicon_url.to_string(),
+        rel: "icon".to_string(),
+        sizes: None,
+        mime_type: None,
+        color: None,
+    }))
+}
+
+async fn fetch_manifest(
+    state: &AppState,
+    url: &str,
+    document_url: &Url,
+) -> Result<ManifestInfo, ServerError> {
+    let manifest_url = Url::parse(url)?;
+    let json = fetch_json(state, url).await?;
+    Ok(manifest::parse(&json, &manifest_url, document_url))
 }
 
-async fn extract_info(html: &str) -> Result<PageInfo, ServerError> {
-    let dom = parse_document(RcDom::default(), Default::default())
-        .from_utf8()
-        .read_from(&mut html.as_bytes())?;
+async fn fetch_oembed(
+    state: &AppState,
+    link: &OEmbedLink,
+    options: &ExtractOptions,
+) -> Result<OEmbed, ServerError> {
+    let url = oembed::with_max_size(Url::parse(&link.href)?, options.maxwidth, options.maxheight);
+    match link.format {
+        OEmbedFormat::Json => Ok(OEmbed::from_json(&fetch_json(state, url.as_str()).await?)),
+        OEmbedFormat::Xml => {
+            let xml = state
+                .send(Method::GET, url.as_str())
+                .await?
+                .error_for_status()?
+                .text()
+                .await?;
+            Ok(OEmbed::from_xml(&xml)?)
+        }
+    }
+}
+
+async fn fetch_json(state: &AppState, url: &str) -> Result<serde_json::Value, ServerError> {
+    let body: serde_json::Value = state
+        .send(Method::GET, url)
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
 
+    Ok(body)
+}
+
+fn extract_info(dom: &RcDom, url: &Url, options: &ExtractOptions) -> (PageInfo, ParseExtras) {
     let mut page_info = PageInfo::default();
+    let mut extras = ParseExtras::default();
     let mut og_tags = HashMap::new();
+    let mut twitter_tags = HashMap::new();
+
+    walk(
+        dom.document.clone(),
+        &mut page_info,
+        &mut extras,
+        &mut og_tags,
+        &mut twitter_tags,
+        false,
+        options.body,
+    );
 
-    walk(dom.document, &mut page_info, &mut og_tags, false);
+    // A tile color seen before its tile image leaves a placeholder behind.
+    if let Some(icons) = &mut page_info.icons {
+        icons.retain(|icon| !icon.href.is_empty());
+        if icons.is_empty() {
+            page_info.icons = None;
+        }
+    }
 
     if !og_tags.is_empty() {
         page_info.og_tags = Some(og_tags);
     }
+    if !twitter_tags.is_empty() {
+        page_info.twitter_tags = Some(twitter_tags);
+    }
+
+    // The document base is the response URL, overridden by the first
+    // `<base href>` in the document (itself resolved against the response URL).
+    let base = find_base_href(&dom.document)
+        .and_then(|href| url.join(&href).ok())
+        .unwrap_or_else(|| url.clone());
+
+    if options.wants("microdata") {
+        let microdata = microdata::extract_microdata(&dom.document, &base);
+        if !microdata.is_empty() {
+            page_info.microdata = Some(microdata);
+        }
+    }
+    if options.wants("rdfa") {
+        let rdfa = microdata::extract_rdfa(&dom.document, &base);
+        if !rdfa.is_empty() {
+            page_info.rdfa = Some(rdfa);
+        }
+    }
+
+    resolve_urls(&mut page_info, &base, options.original_urls);
+    if let Some(image) = &mut extras.hints.image {
+        if let Ok(src) = base.join(image.src.trim()) {
+            image.src = src.to_string();
+        }
+    }
+    extras.hints.json_ld_image = page_info
+        .json_ld
+        .iter()
+        .flatten()
+        .find_map(|item| preview::json_ld_url(item.get("image")?))
+        .and_then(|src| base.join(src.trim()).ok())
+        .map(|src| src.to_string());
+    extras
+        .oembed_links
+        .retain_mut(|link| match base.join(link.href.trim()) {
+            Ok(href) => {
+                link.href = href.to_string();
+                true
+            }
+            Err(_) => false,
+        });
 
-    Ok(page_info)
+    (page_info, extras)
 }
 
 fn walk(
     handle: Handle,
     page_info: &mut PageInfo,
+    extras: &mut ParseExtras,
     og_tags: &mut HashMap<String, serde_json::Value>,
+    twitter_tags: &mut HashMap<String, serde_json::Value>,
     mut is_head: bool,
+    body: bool,
 ) {
     let node = handle;
     if let NodeData::Element {
@@ -168,8 +947,43 @@ fn walk(
             }
         } else if tag_name == "head" {
             is_head = true;
+        } else if tag_name == "html" {
+            for attr in attrs.borrow().iter() {
+                let value = attr.value.trim();
+                match attr.name.local.as_ref() {
+                    "lang" if !value.is_empty() => page_info.lang = Some(value.to_string()),
+                    "dir" if !value.is_empty() => page_info.dir = Some(value.to_ascii_lowercase()),
+                    _ => {}
+                }
+            }
+        } else if tag_name == "h1" && body && extras.hints.h1.is_none() {
+            let text = text_content(&node);
+            let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+            if !text.is_empty() {
+                extras.hints.h1 = Some(text);
+            }
+        } else if tag_name == "img" && body {
+            let attrs = attrs.borrow();
+            let attr = |name: &str| {
+                attrs
+                    .iter()
+                    .find(|attr| attr.name.local.as_ref() == name)
+                    .map(|attr| attr.value.as_ref())
+            };
+            if let Some(src) = attr("src").filter(|src| !src.trim().is_empty()) {
+                extras
+                    .hints
+                    .add_image(src.to_string(), attr("width"), attr("height"));
+            }
         } else if tag_name == "meta" {
             let attrs = attrs.borrow();
+            if let Some((key, value)) = prefixed_meta(&attrs, "property", "article:") {
+                extras
+                    .hints
+                    .article
+                    .entry(key.to_ascii_lowercase())
+                    .or_insert(value);
+            }
             if attrs.iter().any(|attr| {
                 attr.name.local.as_ref() == "name" && attr.value.as_ref() == "description"
             }) {
@@ -179,126 +993,574 @@ fn walk(
                 {
                     page_info.description = Some(content.value.to_string());
                 }
-            } else if attrs.iter().any(|attr| {
-                attr.name.local.as_ref() == "property" && attr.value.as_ref().starts_with("og:")
-            }) {
-                if let Some(property) = attrs
-                    .iter()
-                    .find(|attr| attr.name.local.as_ref() == "property")
+            } else if let Some((name, content)) = attrs
+                .iter()
+                .find(|attr| attr.name.local.as_ref() == "name")
+                .filter(|name| {
+                    name.value
+                        .to_ascii_lowercase()
+                        .starts_with("msapplication-tile")
+                })
+                .zip(
+                    attrs
+                        .iter()
+                        .find(|attr| attr.name.local.as_ref() == "content"),
+                )
+            {
+                let icons = page_info.icons.get_or_insert_with(Vec::new);
+                let tile = match icons
+                    .iter_mut()
+                    .find(|icon| icon.rel.eq_ignore_ascii_case(icons::TILE_IMAGE_REL))
                 {
-                    if let Some(content) = attrs
+                    Some(tile) => tile,
+                    None => {
+                        icons.push(Icon {
+                            href: String::new(),
+                            rel: icons::TILE_IMAGE_REL.to_string(),
+                            sizes: None,
+                            mime_type: None,
+                            color: None,
+                        });
+                        icons.last_mut().unwrap()
+                    }
+                };
+                if name.value.eq_ignore_ascii_case("msapplication-TileImage") {
+                    tile.href = content.value.to_string();
+                } else if name.value.eq_ignore_ascii_case("msapplication-TileColor") {
+                    tile.color = Some(content.value.to_string());
+                }
+            } else if let Some((key, value)) = prefixed_meta(&attrs, "property", "og:") {
+                insert_grouped_tag(og_tags, key, value, &["image", "audio", "video"]);
+            } else if let Some((key, value)) = prefixed_meta(&attrs, "name", "twitter:")
+                .or_else(|| prefixed_meta(&attrs, "property", "twitter:"))
+            {
+                // `twitter:image:src` is the legacy name of `twitter:image`,
+                // and starts an image when it comes first.
+                let key = match key {
+                    "image:src" if !twitter_tags.contains_key("image") => "image",
+                    key => key,
+                };
+                insert_grouped_tag(twitter_tags, key, value, &["image", "player"]);
+            } else if let Some((name, content)) = attrs
+                .iter()
+                .find(|attr| attr.name.local.as_ref() == "name")
+                .zip(
+                    attrs
                         .iter()
-                        .find(|attr| attr.name.local.as_ref() == "content")
-                    {
-                        let full_key = property.value.as_ref();
-                        let key = full_key.trim_start_matches("og:");
-                        let value = content.value.to_string();
-
-                        if key.starts_with("image")
-                            || key.starts_with("audio")
-                            || key.starts_with("video")
-                        {
-                            let main_key = if key.starts_with("image") {
-                                "image"
-                            } else if key.starts_with("audio") {
-                                "audio"
-                            } else {
-                                "video"
-                            };
-                            let entry = og_tags
-                                .entry(main_key.to_string())
-                                .or_insert_with(|| serde_json::Value::Array(Vec::new()));
-
-                            if let Some(array) = entry.as_array_mut() {
-                                if key == main_key {
-                                    array.push(serde_json::json!({ "url": value }));
-                                } else {
-                                    let attr = key.split_once(':').map(|x| x.1).unwrap_or(key);
-                                    if let Some(last_obj) = array.last_mut() {
-                                        if let Some(obj) = last_obj.as_object_mut() {
-                                            obj.insert(
-                                                attr.to_string(),
-                                                serde_json::Value::String(value),
-                                            );
-                                        }
-                                    }
-                                }
-                            }
-                        } else if key == "locale:alternate" {
-                            let entry = og_tags
-                                .entry("locale:alternate".to_string())
-                                .or_insert_with(|| serde_json::Value::Array(Vec::new()));
-
-                            if let Some(array) = entry.as_array_mut() {
-                                array.push(serde_json::Value::String(value));
-                            }
-                        } else {
-                            og_tags.insert(key.to_string(), serde_json::Value::String(value));
+                        .find(|attr| attr.name.local.as_ref() == "content"),
+                )
+            {
+                let content = content.value.trim().to_string();
+                // The first tag of a name wins, as it does for browsers.
+                let field = match name.value.trim().to_ascii_lowercase().as_str() {
+                    "keywords" => {
+                        if page_info.keywords.is_none() {
+                            let keywords: Vec<String> = content
+                                .split(',')
+                                .map(str::trim)
+                                .filter(|keyword| !keyword.is_empty())
+                                .map(str::to_string)
+                                .collect();
+                            page_info.keywords = (!keywords.is_empty()).then_some(keywords);
                         }
+                        None
                     }
+                    "author" => Some(&mut page_info.author),
+                    "robots" => Some(&mut page_info.robots),
+                    "generator" => Some(&mut page_info.generator),
+                    "theme-color" => Some(&mut page_info.theme_color),
+                    "color-scheme" => Some(&mut page_info.color_scheme),
+                    "viewport" => Some(&mut page_info.viewport),
+                    "application-name" => Some(&mut page_info.application_name),
+                    "referrer" => Some(&mut page_info.referrer),
+                    _ => None,
+                };
+                if let Some(field @ None) = field {
+                    *field = Some(content);
+                }
+            } else if attrs.iter().any(|attr| {
+                attr.name.local.as_ref() == "http-equiv"
+                    && attr.value.trim().eq_ignore_ascii_case("refresh")
+            }) && page_info.refresh.is_none()
+            {
+                page_info.refresh = attrs
+                    .iter()
+                    .find(|attr| attr.name.local.as_ref() == "content")
+                    .and_then(|content| links::parse_refresh(&content.value));
+            }
+        } else if tag_name == "script" {
+            let is_json_ld = attrs.borrow().iter().any(|attr| {
+                attr.name.local.as_ref() == "type"
+                    && attr
+                        .value
+                        .trim()
+                        .eq_ignore_ascii_case("application/ld+json")
+            });
+            if is_json_ld {
+                let text: String = node
+                    .children
+                    .borrow()
+                    .iter()
+                    .filter_map(|child| match child.data {
+                        NodeData::Text { ref contents } => Some(contents.borrow().to_string()),
+                        _ => None,
+                    })
+                    .collect();
+                let items = json_ld::parse(&text);
+                if !items.is_empty() {
+                    page_info.json_ld.get_or_insert_with(Vec::new).extend(items);
                 }
             }
         } else if tag_name == "link" {
             let attrs = attrs.borrow();
-            if attrs
-                .iter()
-                .any(|attr| attr.name.local.as_ref() == "rel" && attr.value.as_ref() == "icon")
-            {
-                if let Some(content) = attrs.iter().find(|attr| attr.name.local.as_ref() == "href")
-                {
-                    page_info.favicon = Some(content.value.to_string());
+            let attr = |name: &str| {
+                attrs
+                    .iter()
+                    .find(|attr| attr.name.local.as_ref() == name)
+                    .map(|attr| attr.value.to_string())
+            };
+            if let (Some(rel), Some(href)) = (attr("rel"), attr("href")) {
+                if links::has_rel(&rel, "manifest") {
+                    page_info.manifest = Some(href.clone());
                 }
-            }
-            if attrs
-                .iter()
-                .any(|attr| attr.name.local.as_ref() == "rel" && attr.value.as_ref() == "manifest")
-            {
-                if let Some(content) = attrs.iter().find(|attr| attr.name.local.as_ref() == "href")
-                {
-                    page_info.manifest = Some(content.value.to_string());
+                if icons::is_icon_rel(&rel) {
+                    if links::has_rel(&rel, "icon") {
+                        page_info.favicon = Some(href.clone());
+                    }
+                    page_info.icons.get_or_insert_with(Vec::new).push(Icon {
+                        href,
+                        rel: rel.trim().to_ascii_lowercase(),
+                        sizes: attr("sizes"),
+                        mime_type: attr("type"),
+                        color: attr("color"),
+                    });
+                } else if links::has_rel(&rel, "alternate") {
+                    let mime_type = attr("type");
+                    if let Some(format) = mime_type.as_deref().and_then(OEmbedLink::format_of) {
+                        extras.oembed_links.push(OEmbedLink { href, format });
+                    } else if let Some(mime_type) = mime_type.filter(|t| links::is_feed_type(t)) {
+                        page_info.feeds.get_or_insert_with(Vec::new).push(Feed {
+                            href,
+                            mime_type: mime_type.trim().to_ascii_lowercase(),
+                            title: attr("title"),
+                        });
+                    } else if let Some(hreflang) = attr("hreflang") {
+                        page_info
+                            .alternate_languages
+                            .get_or_insert_with(Vec::new)
+                            .push(AlternateLanguage {
+                                hreflang: hreflang.trim().to_string(),
+                                href,
+                            });
+                    }
+                } else if links::has_rel(&rel, "canonical") && page_info.canonical_url.is_none() {
+                    page_info.canonical_url = Some(href);
+                } else if links::has_rel(&rel, "amphtml") && page_info.amphtml.is_none() {
+                    page_info.amphtml = Some(href);
+                } else if links::has_rel(&rel, "prev") && page_info.prev.is_none() {
+                    page_info.prev = Some(href);
+                } else if links::has_rel(&rel, "next") && page_info.next.is_none() {
+                    page_info.next = Some(href);
                 }
             }
         }
     }
 
     for child in node.children.borrow().iter() {
-        walk(child.clone(), page_info, og_tags, is_head);
+        walk(
+            child.clone(),
+            page_info,
+            extras,
+            og_tags,
+            twitter_tags,
+            is_head,
+            body,
+        );
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use axum::{body, http::StatusCode};
+/// The text of all descendants of `handle`.
+fn text_content(handle: &Handle) -> String {
+    let mut text = String::new();
+    for child in handle.children.borrow().iter() {
+        match child.data {
+            NodeData::Text { ref contents } => text.push_str(&contents.borrow()),
+            NodeData::Element { .. } => text.push_str(&text_content(child)),
+            _ => {}
+        }
+    }
+    text
+}
 
-    #[tokio::test]
-    async fn test_handle_extract_success() {
-        let mut server = mockito::Server::new_async().await;
-        let url = server.url();
+/// Resolves each preview value from the first source of its chain that has
+/// one:
+///
+/// - title: `og:title`, `twitter:title`, `<title>`, the first `<h1>`, the
+///   JSON-LD `headline`, oEmbed
+/// - description: `og:description`, `twitter:description`, the meta
+///   description, JSON-LD, the manifest
+/// - image: `og:image`, `twitter:image`, JSON-LD, the oEmbed thumbnail, the
+///   largest `<img>`
+/// - site_name: `og:site_name`, `application-name`, the manifest `name` and
+///   `short_name`, the oEmbed provider, the JSON-LD publisher
+/// - type: `og:type`, the JSON-LD `@type`
+/// - url: `og:url`, the canonical URL, the final URL
+/// - author: the `author` and `article:author` meta tags, JSON-LD, oEmbed,
+///   `twitter:creator`
+/// - published_time: `article:published_time`, JSON-LD `datePublished`
+/// - theme_color: `theme-color`, the manifest, `msapplication-TileColor`
+fn build_preview(page_info: &PageInfo, hints: &DocumentHints) -> Preview {
+    fn tag<'a>(tags: &'a Option<HashMap<String, serde_json::Value>>, key: &str) -> Option<&'a str> {
+        match tags.as_ref()?.get(key)? {
+            serde_json::Value::String(value) => Some(value),
+            // Grouped tags such as `og:image` are arrays of `{url, ...}`.
+            serde_json::Value::Array(items) => items.first()?.get("url")?.as_str(),
+            _ => None,
+        }
+    }
+    let og = |key: &str| tag(&page_info.og_tags, key);
+    let twitter = |key: &str| tag(&page_info.twitter_tags, key);
+    let article = |key: &str| hints.article.get(key).map(String::as_str);
+    let tile_color = page_info
+        .icons
+        .iter()
+        .flatten()
+        .find(|icon| icon.rel.eq_ignore_ascii_case(icons::TILE_IMAGE_REL))
+        .and_then(|icon| icon.color.as_deref());
+    let json_ld = |key: &str| {
+        page_info
+            .json_ld
+            .iter()
+            .flatten()
+            .find_map(|item| preview::json_ld_text(item.get(key)?))
+    };
+    let manifest = page_info.manifest_info.as_ref();
+    let oembed = page_info.oembed.as_ref();
 
-        let _m = server
-            .mock("GET", "/")
-            .with_status(200)
-            .with_header("content-type", "text/html")
-            .with_body(
-                r#"
-                <html>
-                <head>
-                    <title>Test Page</title>
-                    <meta name="description" content="This is a test page">
-                    <link rel="icon" href="/favicon.ico">
-                    <meta property="og:title" content="OG Test Title">
-                    <meta property="og:description" content="OG Test Description">
-                </head>
-                <body></body>
-                </html>
+    Preview {
+        title: preview::first([
+            ("og:title", og("title")),
+            ("twitter:title", twitter("title")),
+            ("title", page_info.title.as_deref()),
+            ("h1", hints.h1.as_deref()),
+            ("json_ld:headline", json_ld("headline")),
+            ("oembed:title", oembed.and_then(|o| o.title.as_deref())),
+        ]),
+        description: preview::first([
+            ("og:description", og("description")),
+            ("twitter:description", twitter("description")),
+            ("meta:description", page_info.description.as_deref()),
+            ("json_ld:description", json_ld("description")),
+            (
+                "manifest:description",
+                manifest.and_then(|m| m.description.as_deref()),
+            ),
+        ]),
+        image: preview::first([
+            ("og:image", og("image")),
+            ("twitter:image", twitter("image")),
+            ("json_ld:image", hints.json_ld_image.as_deref()),
+            (
+                "oembed:thumbnail_url",
+                oembed.and_then(|o| o.thumbnail_url.as_deref()),
+            ),
+            ("img", hints.image.as_ref().map(|image| image.src.as_str())),
+        ]),
+        site_name: preview::first([
+            ("og:site_name", og("site_name")),
+            (
+                "meta:application-name",
+                page_info.application_name.as_deref(),
+            ),
+            ("manifest:name", manifest.and_then(|m| m.name.as_deref())),
+            (
+                "manifest:short_name",
+                manifest.and_then(|m| m.short_name.as_deref()),
+            ),
+            (
+                "oembed:provider_name",
+                oembed.and_then(|o| o.provider_name.as_deref()),
+            ),
+            ("json_ld:publisher", json_ld("publisher")),
+        ]),
+        page_type: preview::first([("og:type", og("type")), ("json_ld:@type", json_ld("@type"))]),
+        url: preview::first([
+            ("og:url", og("url")),
+            ("canonical_url", page_info.canonical_url.as_deref()),
+            ("final_url", page_info.final_url.as_deref()),
+        ]),
+        author: preview::first([
+            ("meta:author", page_info.author.as_deref()),
+            ("meta:article:author", article("author")),
+            ("json_ld:author", json_ld("author")),
+            (
+                "oembed:author_name",
+                oembed.and_then(|o| o.author_name.as_deref()),
+            ),
+            ("twitter:creator", twitter("creator")),
+        ]),
+        published_time: preview::first([
+            ("meta:article:published_time", article("published_time")),
+            ("json_ld:datePublished", json_ld("datePublished")),
+        ]),
+        theme_color: preview::first([
+            ("meta:theme-color", page_info.theme_color.as_deref()),
+            (
+                "manifest:theme_color",
+                manifest.and_then(|m| m.theme_color.as_deref()),
+            ),
+            ("meta:msapplication-tilecolor", tile_color),
+        ]),
+    }
+}
+
+fn find_base_href(handle: &Handle) -> Option<String> {
+    if let NodeData::Element {
+        ref name,
+        ref attrs,
+        ..
+    } = handle.data
+    {
+        if name.local.as_ref() == "base" {
+            if let Some(href) = attrs
+                .borrow()
+                .iter()
+                .find(|attr| attr.name.local.as_ref() == "href")
+            {
+                return Some(href.value.to_string());
+            }
+        }
+    }
+    handle.children.borrow().iter().find_map(find_base_href)
+}
+
+/// Makes every URL-typed field of `page_info` absolute by joining it with
+/// `base`. When `keep_originals` is set, the raw values are reported in
+/// `original_urls`, keyed by the path of the field they were taken from.
+fn resolve_urls(page_info: &mut PageInfo, base: &Url, keep_originals: bool) {
+    let mut originals = HashMap::new();
+    let mut resolve = |path: String, value: &mut String| {
+        if let Ok(resolved) = base.join(value.trim()) {
+            let resolved = resolved.to_string();
+            if resolved != *value {
+                let original = std::mem::replace(value, resolved);
+                originals.insert(path, original);
+            }
+        }
+    };
+
+    if let Some(favicon) = &mut page_info.favicon {
+        resolve("favicon".to_string(), favicon);
+    }
+    if let Some(manifest) = &mut page_info.manifest {
+        resolve("manifest".to_string(), manifest);
+    }
+    if let Some(canonical_url) = &mut page_info.canonical_url {
+        resolve("canonical_url".to_string(), canonical_url);
+    }
+    for (name, url) in [
+        ("amphtml", &mut page_info.amphtml),
+        ("prev", &mut page_info.prev),
+        ("next", &mut page_info.next),
+    ] {
+        if let Some(url) = url {
+            resolve(name.to_string(), url);
+        }
+    }
+    for (index, alternate) in page_info
+        .alternate_languages
+        .iter_mut()
+        .flatten()
+        .enumerate()
+    {
+        resolve(
+            format!("alternate_languages.{}.href", index),
+            &mut alternate.href,
+        );
+    }
+    for (index, feed) in page_info.feeds.iter_mut().flatten().enumerate() {
+        resolve(format!("feeds.{}.href", index), &mut feed.href);
+    }
+    if let Some(url) = page_info
+        .refresh
+        .as_mut()
+        .and_then(|refresh| refresh.url.as_mut())
+    {
+        resolve("refresh.url".to_string(), url);
+    }
+    for (index, icon) in page_info.icons.iter_mut().flatten().enumerate() {
+        resolve(format!("icons.{}.href", index), &mut icon.href);
+    }
+
+    let tag_sets = [
+        (
+            "og_tags",
+            &mut page_info.og_tags,
+            &["url"][..],
+            &["image", "audio", "video"][..],
+            &["url", "secure_url"][..],
+        ),
+        (
+            "twitter_tags",
+            &mut page_info.twitter_tags,
+            &[][..],
+            &["image", "player"][..],
+            &["url", "src", "stream"][..],
+        ),
+    ];
+    for (prefix, tags, keys, groups, group_keys) in tag_sets {
+        let Some(tags) = tags else {
+            continue;
+        };
+        for key in keys {
+            if let Some(serde_json::Value::String(value)) = tags.get_mut(*key) {
+                resolve(format!("{}.{}", prefix, key), value);
+            }
+        }
+        for group in groups {
+            let Some(serde_json::Value::Array(items)) = tags.get_mut(*group) else {
+                continue;
+            };
+            for (index, item) in items.iter_mut().enumerate() {
+                for key in group_keys {
+                    if let Some(serde_json::Value::String(value)) = item.get_mut(*key) {
+                        resolve(format!("{}.{}.{}.{}", prefix, group, index, key), value);
+                    }
+                }
+            }
+        }
+    }
+
+    if keep_originals && !originals.is_empty() {
+        page_info.original_urls = Some(originals);
+    }
+}
+
+/// Returns the key (without `prefix`) and content of a `<meta>` tag whose
+/// `attr_name` attribute starts with `prefix`.
+fn prefixed_meta<'a>(
+    attrs: &'a [html5ever::Attribute],
+    attr_name: &str,
+    prefix: &str,
+) -> Option<(&'a str, String)> {
+    let property = attrs
+        .iter()
+        .find(|attr| attr.name.local.as_ref() == attr_name)?;
+    let key = property.value.as_ref().strip_prefix(prefix)?;
+    let content = attrs
+        .iter()
+        .find(|attr| attr.name.local.as_ref() == "content")?;
+    Some((key, content.value.to_string()))
+}
+
+/// Inserts a structured meta tag into `tags`. Keys starting with one of
+/// `groups` are collected into an array of objects: the bare key starts a new
+/// object and sub-properties (e.g. `image:width`) are attached to the last one,
+/// or start one of their own when they come first.
+fn insert_grouped_tag(
+    tags: &mut HashMap<String, serde_json::Value>,
+    key: &str,
+    value: String,
+    groups: &[&str],
+) {
+    if let Some(main_key) = groups.iter().find(|group| key.starts_with(**group)) {
+        let entry = tags
+            .entry(main_key.to_string())
+            .or_insert_with(|| serde_json::Value::Array(Vec::new()));
+
+        if let Some(array) = entry.as_array_mut() {
+            if key == *main_key {
+                array.push(serde_json::json!({ "url": value }));
+            } else {
+                let attr = key.split_once(':').map(|x| x.1).unwrap_or(key);
+                if array.is_empty() {
+                    array.push(serde_json::json!({}));
+                }
+                if let Some(obj) = array.last_mut().and_then(|last| last.as_object_mut()) {
+                    obj.insert(attr.to_string(), serde_json::Value::String(value));
+                }
+            }
+        }
+    } else if key == "locale:alternate" {
+        let entry = tags
+            .entry("locale:alternate".to_string())
+            .or_insert_with(|| serde_json::Value::Array(Vec::new()));
+
+        if let Some(array) = entry.as_array_mut() {
+            array.push(serde_json::Value::String(value));
+        }
+    } else {
+        tags.insert(key.to_string(), serde_json::Value::String(value));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cache::{CacheConfig, MemoryStore, MetadataCache},
+        host_policy::HostPolicyStore,
+        jobs::{self, JobConfig, JobQueue, JobState},
+        oembed::ProviderRegistry,
+        ssrf::AddressGuard,
+        state::{BatchConfig, HttpClientConfig},
+    };
+    use axum::{body, extract::FromRequestParts, http::StatusCode};
+    use std::time::Duration;
+
+    /// mockito listens on loopback, which the address guard blocks by default.
+    fn loopback_guard() -> AddressGuard {
+        AddressGuard::new(vec!["127.0.0.0/8".parse().unwrap()])
+    }
+
+    fn test_app_state() -> AppState {
+        AppState::new(
+            loopback_guard(),
+            HostPolicyStore::default(),
+            &HttpClientConfig::default(),
+        )
+        .unwrap()
+    }
+
+    fn test_state() -> State<Arc<AppState>> {
+        State(Arc::new(test_app_state()))
+    }
+
+    #[tokio::test]
+    async fn test_handle_extract_success() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let _m = server
+            .mock("GET", "/")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body(
+                r#"
+                <html>
+                <head>
+                    <title>Test Page</title>
+                    <meta name="description" content="This is a test page">
+                    <link rel="icon" href="/favicon.ico">
+                    <meta property="og:title" content="OG Test Title">
+                    <meta property="og:description" content="OG Test Description">
+                </head>
+                <body></body>
+                </html>
             "#,
             )
             .create_async()
             .await;
 
         let url_path = UrlPath { url: url.clone() };
-        let result = handle_extract(ValidatedPath(url_path)).await.unwrap();
+        let result = handle_extract(
+            test_state(),
+            ValidatedPath(url_path),
+            ValidatedQuery(ExtractOptions::default()),
+            HeaderMap::new(),
+        )
+        .await
+        .unwrap();
 
         assert_eq!(result.status(), StatusCode::OK);
 
@@ -312,7 +1574,7 @@ mod tests {
             page_info.description,
             Some("This is a test page".to_string())
         );
-        assert_eq!(page_info.favicon, Some("/favicon.ico".to_string()));
+        assert_eq!(page_info.favicon, Some(format!("{}/favicon.ico", url)));
 
         let og_tags = page_info.og_tags.unwrap();
         assert_eq!(
@@ -337,7 +1599,14 @@ mod tests {
             .await;
 
         let url_path = UrlPath { url: url.clone() };
-        let result = handle_extract(ValidatedPath(url_path)).await.unwrap();
+        let result = handle_extract(
+            test_state(),
+            ValidatedPath(url_path),
+            ValidatedQuery(ExtractOptions::default()),
+            HeaderMap::new(),
+        )
+        .await
+        .unwrap();
 
         assert_eq!(result.status(), StatusCode::NOT_FOUND);
 
@@ -355,7 +1624,13 @@ mod tests {
         let url_path = UrlPath {
             url: "not a valid url".to_string(),
         };
-        let result = handle_extract(ValidatedPath(url_path)).await;
+        let result = handle_extract(
+            test_state(),
+            ValidatedPath(url_path),
+            ValidatedQuery(ExtractOptions::default()),
+            HeaderMap::new(),
+        )
+        .await;
 
         assert!(result.is_err());
 
@@ -405,7 +1680,14 @@ mod tests {
             .await;
 
         let url_path = UrlPath { url: url.clone() };
-        let result = handle_extract(ValidatedPath(url_path)).await.unwrap();
+        let result = handle_extract(
+            test_state(),
+            ValidatedPath(url_path),
+            ValidatedQuery(ExtractOptions::default()),
+            HeaderMap::new(),
+        )
+        .await
+        .unwrap();
 
         assert_eq!(result.status(), StatusCode::OK);
 
@@ -448,7 +1730,14 @@ mod tests {
             .await;
 
         let url_path = UrlPath { url: url.clone() };
-        let result = handle_extract(ValidatedPath(url_path)).await.unwrap();
+        let result = handle_extract(
+            test_state(),
+            ValidatedPath(url_path),
+            ValidatedQuery(ExtractOptions::default()),
+            HeaderMap::new(),
+        )
+        .await
+        .unwrap();
 
         assert_eq!(result.status(), StatusCode::OK);
 
@@ -462,15 +1751,2496 @@ mod tests {
         assert_eq!(images.len(), 2);
         assert_eq!(
             images[0].get("url").unwrap().as_str().unwrap(),
-            "image1.jpg"
+            format!("{}/image1.jpg", url)
         );
         assert_eq!(images[0].get("width").unwrap().as_str().unwrap(), "800");
         assert_eq!(images[0].get("height").unwrap().as_str().unwrap(), "600");
         assert_eq!(
             images[1].get("url").unwrap().as_str().unwrap(),
-            "image2.jpg"
+            format!("{}/image2.jpg", url)
         );
         assert_eq!(images[1].get("width").unwrap().as_str().unwrap(), "1200");
         assert_eq!(images[1].get("height").unwrap().as_str().unwrap(), "900");
     }
+
+    #[tokio::test]
+    async fn test_handle_extract_twitter_tags() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let _m = server
+            .mock("GET", "/")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body(
+                r#"
+                <html>
+                <head>
+                    <title>Test Page</title>
+                    <meta name="twitter:card" content="player">
+                    <meta name="twitter:site" content="@example">
+                    <meta name="twitter:image" content="image.jpg">
+                    <meta name="twitter:image:alt" content="An image">
+                    <meta name="twitter:player" content="https://example.com/player">
+                    <meta name="twitter:player:width" content="480">
+                    <meta name="twitter:player:height" content="360">
+                </head>
+                <body></body>
+                </html>
+            "#,
+            )
+            .create_async()
+            .await;
+
+        let url_path = UrlPath { url: url.clone() };
+        let result = handle_extract(
+            test_state(),
+            ValidatedPath(url_path),
+            ValidatedQuery(ExtractOptions::default()),
+            HeaderMap::new(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(result.status(), StatusCode::OK);
+
+        let body = body::to_bytes(result.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let page_info: PageInfo = serde_json::from_slice(&body).unwrap();
+
+        assert!(page_info.og_tags.is_none());
+        let twitter_tags = page_info.twitter_tags.unwrap();
+        assert_eq!(
+            twitter_tags.get("card").unwrap().as_str().unwrap(),
+            "player"
+        );
+        assert_eq!(
+            twitter_tags.get("site").unwrap().as_str().unwrap(),
+            "@example"
+        );
+
+        let images = twitter_tags.get("image").unwrap().as_array().unwrap();
+        assert_eq!(images.len(), 1);
+        assert_eq!(
+            images[0].get("url").unwrap().as_str().unwrap(),
+            format!("{}/image.jpg", url)
+        );
+        assert_eq!(images[0].get("alt").unwrap().as_str().unwrap(), "An image");
+
+        let players = twitter_tags.get("player").unwrap().as_array().unwrap();
+        assert_eq!(players.len(), 1);
+        assert_eq!(
+            players[0].get("url").unwrap().as_str().unwrap(),
+            "https://example.com/player"
+        );
+        assert_eq!(players[0].get("width").unwrap().as_str().unwrap(), "480");
+        assert_eq!(players[0].get("height").unwrap().as_str().unwrap(), "360");
+    }
+
+    #[tokio::test]
+    async fn test_handle_extract_twitter_image_src() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let _m = server
+            .mock("GET", "/")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body(
+                r#"
+                <html>
+                <head>
+                    <meta name="twitter:image:src" content="legacy.jpg">
+                    <meta name="twitter:image:alt" content="Legacy">
+                    <meta name="twitter:player:width" content="480">
+                </head>
+                </html>
+            "#,
+            )
+            .create_async()
+            .await;
+
+        let url_path = UrlPath { url: url.clone() };
+        let result = handle_extract(
+            test_state(),
+            ValidatedPath(url_path),
+            ValidatedQuery(ExtractOptions::default()),
+            HeaderMap::new(),
+        )
+        .await
+        .unwrap();
+        let body = body::to_bytes(result.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let page_info: PageInfo = serde_json::from_slice(&body).unwrap();
+
+        let twitter_tags = page_info.twitter_tags.unwrap();
+        assert_eq!(
+            twitter_tags["image"],
+            serde_json::json!([{ "url": format!("{}/legacy.jpg", url), "alt": "Legacy" }])
+        );
+        // A sub-property without its bare key still gets an object.
+        assert_eq!(
+            twitter_tags["player"],
+            serde_json::json!([{ "width": "480" }])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_handle_extract_json_ld() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let _m = server
+            .mock("GET", "/")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body(
+                r#"
+                <html>
+                <head>
+                    <title>Test Page</title>
+                    <script type="application/ld+json">
+                    {
+                        "@context": "https://schema.org",
+                        "@graph": [
+                            {"@type": "Organization", "name": "Example"},
+                            {"@type": "BreadcrumbList", "itemListElement": []}
+                        ]
+                    }
+                    </script>
+                </head>
+                <body>
+                    <script type="application/ld+json">
+                    {"@context": "https://schema.org", "@type": "Article", "headline": "Hello",}
+                    </script>
+                </body>
+                </html>
+            "#,
+            )
+            .create_async()
+            .await;
+
+        let url_path = UrlPath { url: url.clone() };
+        let options = ExtractOptions {
+            body: true,
+            ..Default::default()
+        };
+        let result = handle_extract(
+            test_state(),
+            ValidatedPath(url_path),
+            ValidatedQuery(options),
+            HeaderMap::new(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(result.status(), StatusCode::OK);
+
+        let body = body::to_bytes(result.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let page_info: PageInfo = serde_json::from_slice(&body).unwrap();
+
+        let json_ld = page_info.json_ld.unwrap();
+        assert_eq!(json_ld.len(), 3);
+        assert_eq!(json_ld[0]["@type"], "Organization");
+        assert_eq!(json_ld[1]["@type"], "BreadcrumbList");
+        assert_eq!(json_ld[2]["@type"], "Article");
+        assert_eq!(json_ld[2]["headline"], "Hello");
+    }
+
+    #[tokio::test]
+    async fn test_handle_extract_microdata_and_rdfa() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let _m = server
+            .mock("GET", "/")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body(
+                r##"
+                <html>
+                <head>
+                    <title>Test Page</title>
+                </head>
+                <body>
+                    <div itemscope itemtype="https://schema.org/Product" itemref="offer">
+                        <span itemprop="name">Widget</span>
+                        <img itemprop="image" src="widget.jpg">
+                        <meta itemprop="sku" content="W-1">
+                        <div itemprop="brand" itemscope itemtype="https://schema.org/Brand">
+                            <span itemprop="name">Acme</span>
+                        </div>
+                    </div>
+                    <div id="offer" itemprop="offers" itemscope itemtype="https://schema.org/Offer">
+                        <span itemprop="price">9.99</span>
+                    </div>
+                    <div vocab="https://schema.org/" typeof="Person" resource="#jane">
+                        <span property="name">Jane Doe</span>
+                        <a property="url" href="https://example.com/jane">Home</a>
+                        <div property="address" typeof="PostalAddress">
+                            <span property="addressLocality">Springfield</span>
+                        </div>
+                    </div>
+                </body>
+                </html>
+            "##,
+            )
+            .create_async()
+            .await;
+
+        let url_path = UrlPath { url: url.clone() };
+        let options = ExtractOptions {
+            body: true,
+            ..Default::default()
+        };
+        let result = handle_extract(
+            test_state(),
+            ValidatedPath(url_path),
+            ValidatedQuery(options),
+            HeaderMap::new(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(result.status(), StatusCode::OK);
+
+        let body = body::to_bytes(result.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let page_info: PageInfo = serde_json::from_slice(&body).unwrap();
+
+        let microdata = page_info.microdata.unwrap();
+        assert_eq!(microdata.len(), 1);
+        let product = &microdata[0];
+        assert_eq!(product["type"][0], "https://schema.org/Product");
+        assert_eq!(product["properties"]["name"][0], "Widget");
+        assert_eq!(
+            product["properties"]["image"][0],
+            format!("{}/widget.jpg", url)
+        );
+        assert_eq!(product["properties"]["sku"][0], "W-1");
+        assert_eq!(
+            product["properties"]["brand"][0]["properties"]["name"][0],
+            "Acme"
+        );
+        assert_eq!(
+            product["properties"]["offers"][0]["properties"]["price"][0],
+            "9.99"
+        );
+
+        let rdfa = page_info.rdfa.unwrap();
+        assert_eq!(rdfa.len(), 1);
+        let person = &rdfa[0];
+        assert_eq!(person["type"][0], "https://schema.org/Person");
+        assert_eq!(person["id"], format!("{}/#jane", url));
+        assert_eq!(person["properties"]["name"][0], "Jane Doe");
+        assert_eq!(person["properties"]["url"][0], "https://example.com/jane");
+        assert_eq!(
+            person["properties"]["address"][0]["type"][0],
+            "https://schema.org/PostalAddress"
+        );
+        assert_eq!(
+            person["properties"]["address"][0]["properties"]["addressLocality"][0],
+            "Springfield"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_handle_extract_resolves_urls() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let _m = server
+            .mock("GET", "/articles/post")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body(
+                r#"
+                <html>
+                <head>
+                    <base href="/static/">
+                    <title>Test Page</title>
+                    <link rel="icon" href="favicon.png">
+                    <meta property="og:url" content="../articles/post">
+                    <meta property="og:image" content="img/cover.jpg">
+                    <meta property="og:image:secure_url" content="//cdn.example.com/cover.jpg">
+                    <meta name="twitter:image" content="https://example.com/absolute.jpg">
+                </head>
+                <body></body>
+                </html>
+            "#,
+            )
+            .create_async()
+            .await;
+
+        let url_path = UrlPath {
+            url: format!("{}/articles/post", url),
+        };
+        let options = ExtractOptions {
+            original_urls: true,
+            ..Default::default()
+        };
+        let result = handle_extract(
+            test_state(),
+            ValidatedPath(url_path),
+            ValidatedQuery(options),
+            HeaderMap::new(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(result.status(), StatusCode::OK);
+
+        let body = body::to_bytes(result.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let page_info: PageInfo = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(
+            page_info.favicon,
+            Some(format!("{}/static/favicon.png", url))
+        );
+
+        let og_tags = page_info.og_tags.unwrap();
+        assert_eq!(
+            og_tags.get("url").unwrap().as_str().unwrap(),
+            format!("{}/articles/post", url)
+        );
+        let images = og_tags.get("image").unwrap().as_array().unwrap();
+        assert_eq!(
+            images[0].get("url").unwrap().as_str().unwrap(),
+            format!("{}/static/img/cover.jpg", url)
+        );
+        assert_eq!(
+            images[0].get("secure_url").unwrap().as_str().unwrap(),
+            "http://cdn.example.com/cover.jpg"
+        );
+
+        let original_urls = page_info.original_urls.unwrap();
+        assert_eq!(original_urls.get("favicon").unwrap(), "favicon.png");
+        assert_eq!(
+            original_urls.get("og_tags.url").unwrap(),
+            "../articles/post"
+        );
+        assert_eq!(
+            original_urls.get("og_tags.image.0.url").unwrap(),
+            "img/cover.jpg"
+        );
+        assert!(!original_urls.contains_key("twitter_tags.image.0.url"));
+    }
+
+    #[tokio::test]
+    async fn test_handle_extract_icons() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let _m = server
+            .mock("GET", "/")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body(
+                r##"
+                <html>
+                <head>
+                    <title>Test Page</title>
+                    <meta name="msapplication-TileColor" content="#da532c">
+                    <link rel="shortcut icon" href="/favicon.ico">
+                    <link rel="icon" type="image/png" sizes="32x32" href="/icon-32.png">
+                    <link rel="apple-touch-icon" sizes="180x180" href="/apple-touch-icon.png">
+                    <link rel="mask-icon" href="/safari.svg" color="#5bbad5">
+                    <meta name="msapplication-TileImage" content="/mstile-144.png">
+                    <link rel="manifest" href="/manifest.json">
+                </head>
+                <body></body>
+                </html>
+            "##,
+            )
+            .create_async()
+            .await;
+
+        let _manifest_mock = server
+            .mock("GET", "/manifest.json")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(
+                r#"
+                {
+                    "name": "Test App",
+                    "icons": [
+                        {"src": "icons/512.png", "sizes": "512x512", "type": "image/png"}
+                    ]
+                }
+            "#,
+            )
+            .create_async()
+            .await;
+
+        let url_path = UrlPath { url: url.clone() };
+        let options = ExtractOptions {
+            icon_size: Some(192),
+            ..Default::default()
+        };
+        let result = handle_extract(
+            test_state(),
+            ValidatedPath(url_path),
+            ValidatedQuery(options),
+            HeaderMap::new(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(result.status(), StatusCode::OK);
+
+        let body = body::to_bytes(result.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let page_info: PageInfo = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(page_info.favicon, Some(format!("{}/icon-32.png", url)));
+
+        let icons = page_info.icons.unwrap();
+        let rels: Vec<&str> = icons.iter().map(|icon| icon.rel.as_str()).collect();
+        assert_eq!(
+            rels,
+            vec![
+                "msapplication-tileimage",
+                "shortcut icon",
+                "icon",
+                "apple-touch-icon",
+                "mask-icon",
+                "manifest"
+            ]
+        );
+        assert_eq!(icons[0].href, format!("{}/mstile-144.png", url));
+        assert_eq!(icons[0].color, Some("#da532c".to_string()));
+        assert_eq!(icons[2].sizes, Some("32x32".to_string()));
+        assert_eq!(icons[2].mime_type, Some("image/png".to_string()));
+        assert_eq!(icons[4].color, Some("#5bbad5".to_string()));
+        assert_eq!(icons[5].href, format!("{}/icons/512.png", url));
+
+        assert_eq!(
+            page_info.best_icon.unwrap().href,
+            format!("{}/icons/512.png", url)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_handle_extract_favicon_fallback() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let _m = server
+            .mock("GET", "/")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body("<html><head><title>Test Page</title></head></html>")
+            .create_async()
+            .await;
+
+        let _favicon_mock = server
+            .mock("HEAD", "/favicon.ico")
+            .with_status(200)
+            .create_async()
+            .await;
+
+        let url_path = UrlPath { url: url.clone() };
+        let result = handle_extract(
+            test_state(),
+            ValidatedPath(url_path),
+            ValidatedQuery(ExtractOptions::default()),
+            HeaderMap::new(),
+        )
+        .await
+        .unwrap();
+
+        let body = body::to_bytes(result.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let page_info: PageInfo = serde_json::from_slice(&body).unwrap();
+
+        let icons = page_info.icons.unwrap();
+        assert_eq!(icons.len(), 1);
+        assert_eq!(icons[0].href, format!("{}/favicon.ico", url));
+        assert_eq!(
+            page_info.best_icon.unwrap().href,
+            format!("{}/favicon.ico", url)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_handle_extract_missing_favicon() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let _m = server
+            .mock("GET", "/")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body("<html><head><title>Test Page</title></head></html>")
+            .create_async()
+            .await;
+
+        for (status, warned) in [(404, false), (410, false), (500, true)] {
+            let favicon_mock = server
+                .mock("HEAD", "/favicon.ico")
+                .with_status(status)
+                .create_async()
+                .await;
+
+            let url_path = UrlPath { url: url.clone() };
+            let result = handle_extract(
+                test_state(),
+                ValidatedPath(url_path),
+                ValidatedQuery(ExtractOptions::default()),
+                HeaderMap::new(),
+            )
+            .await
+            .unwrap();
+
+            let body = body::to_bytes(result.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            let page_info: PageInfo = serde_json::from_slice(&body).unwrap();
+
+            assert!(page_info.icons.is_none());
+            assert_eq!(page_info.warnings.is_some(), warned, "status {}", status);
+            favicon_mock.remove_async().await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_handle_extract_favicon_redirect_loop() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let _m = server
+            .mock("GET", "/")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body("<html><head><title>Test Page</title></head></html>")
+            .create_async()
+            .await;
+        let _favicon_mock = server
+            .mock("HEAD", "/favicon.ico")
+            .with_status(302)
+            .with_header("location", "/favicon.ico")
+            .create_async()
+            .await;
+
+        let url_path = UrlPath { url: url.clone() };
+        let result = handle_extract(
+            test_state(),
+            ValidatedPath(url_path),
+            ValidatedQuery(ExtractOptions::default()),
+            HeaderMap::new(),
+        )
+        .await
+        .unwrap();
+
+        let body = body::to_bytes(result.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let page_info: PageInfo = serde_json::from_slice(&body).unwrap();
+
+        assert!(page_info.icons.is_none());
+        let warnings = page_info.warnings.unwrap();
+        assert_eq!(warnings[0].resource, "favicon");
+        assert!(warnings[0].message.contains("redirects"));
+    }
+
+    #[tokio::test]
+    async fn test_handle_extract_manifest_info() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let _m = server
+            .mock("GET", "/app/")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body(
+                r#"
+                <html>
+                <head>
+                    <link rel="Manifest preload" href="/static/manifest.webmanifest">
+                </head>
+                <body></body>
+                </html>
+            "#,
+            )
+            .create_async()
+            .await;
+
+        let _manifest_mock = server
+            .mock("GET", "/static/manifest.webmanifest")
+            .with_status(200)
+            .with_header("content-type", "application/manifest+json")
+            .with_body(
+                r##"
+                {
+                    "name": "Test App",
+                    "short_name": "Test",
+                    "description": "A test app",
+                    "start_url": "../app/?source=pwa",
+                    "scope": "../app/",
+                    "display": "standalone",
+                    "theme_color": "#123456",
+                    "background_color": "#ffffff",
+                    "lang": "en",
+                    "dir": "sideways",
+                    "categories": ["Productivity", 42],
+                    "icons": [{"src": "icon.png", "sizes": "192x192", "purpose": "maskable"}],
+                    "screenshots": [{"src": "/shot.png", "form_factor": "wide", "label": "Home"}],
+                    "shortcuts": [
+                        {"name": "New", "url": "../app/new", "icons": [{"src": "new.png"}]},
+                        {"name": "Outside", "url": "/elsewhere"}
+                    ]
+                }
+            "##,
+            )
+            .create_async()
+            .await;
+
+        let url_path = UrlPath {
+            url: format!("{}/app/", url),
+        };
+        let result = handle_extract(
+            test_state(),
+            ValidatedPath(url_path),
+            ValidatedQuery(ExtractOptions::default()),
+            HeaderMap::new(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(result.status(), StatusCode::OK);
+
+        let body = body::to_bytes(result.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let page_info: PageInfo = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(page_info.name, Some("Test App".to_string()));
+        assert_eq!(page_info.short_name, Some("Test".to_string()));
+
+        let manifest_info = page_info.manifest_info.unwrap();
+        assert_eq!(manifest_info.description, Some("A test app".to_string()));
+        assert_eq!(
+            manifest_info.start_url,
+            Some(format!("{}/app/?source=pwa", url))
+        );
+        assert_eq!(manifest_info.scope, Some(format!("{}/app/", url)));
+        assert_eq!(manifest_info.display, Some("standalone".to_string()));
+        assert_eq!(manifest_info.theme_color, Some("#123456".to_string()));
+        assert_eq!(manifest_info.background_color, Some("#ffffff".to_string()));
+        assert_eq!(manifest_info.lang, Some("en".to_string()));
+        assert_eq!(manifest_info.dir, None);
+        assert_eq!(manifest_info.categories, vec!["productivity"]);
+
+        assert_eq!(
+            manifest_info.icons[0].src,
+            format!("{}/static/icon.png", url)
+        );
+        assert_eq!(manifest_info.icons[0].purpose, Some("maskable".to_string()));
+        assert_eq!(
+            manifest_info.screenshots[0].src,
+            format!("{}/shot.png", url)
+        );
+        assert_eq!(
+            manifest_info.screenshots[0].form_factor,
+            Some("wide".to_string())
+        );
+
+        assert_eq!(manifest_info.shortcuts.len(), 1);
+        assert_eq!(manifest_info.shortcuts[0].name, "New");
+        assert_eq!(manifest_info.shortcuts[0].url, format!("{}/app/new", url));
+        assert_eq!(
+            manifest_info.shortcuts[0].icons[0].src,
+            format!("{}/static/new.png", url)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_handle_extract_manifest_failure() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let _m = server
+            .mock("GET", "/")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body(
+                r#"
+                <html>
+                <head>
+                    <title>Test Page</title>
+                    <link rel="icon" href="/favicon.ico">
+                    <link rel="manifest" href="/manifest.json">
+                </head>
+                <body></body>
+                </html>
+            "#,
+            )
+            .create_async()
+            .await;
+
+        let _manifest_mock = server
+            .mock("GET", "/manifest.json")
+            .with_status(404)
+            .create_async()
+            .await;
+
+        let url_path = UrlPath { url: url.clone() };
+        let result = handle_extract(
+            test_state(),
+            ValidatedPath(url_path),
+            ValidatedQuery(ExtractOptions::default()),
+            HeaderMap::new(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(result.status(), StatusCode::OK);
+
+        let body = body::to_bytes(result.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let page_info: PageInfo = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(page_info.title, Some("Test Page".to_string()));
+        assert!(page_info.manifest_info.is_none());
+
+        let warnings = page_info.warnings.unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].resource, "manifest");
+        assert_eq!(warnings[0].url, format!("{}/manifest.json", url));
+        assert!(warnings[0].message.contains("404"));
+    }
+
+    #[tokio::test]
+    async fn test_handle_extract_oembed() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let _m = server
+            .mock("GET", "/watch")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body(
+                r#"
+                <html>
+                <head>
+                    <title>Test Video</title>
+                    <link rel="alternate" type="text/xml+oembed" href="/oembed?format=xml">
+                    <link rel="alternate" type="application/json+oembed" href="/oembed?format=json&maxwidth=100">
+                </head>
+                <body></body>
+                </html>
+            "#,
+            )
+            .create_async()
+            .await;
+
+        let _oembed_mock = server
+            .mock("GET", "/oembed")
+            .match_query(mockito::Matcher::AllOf(vec![
+                mockito::Matcher::UrlEncoded("format".into(), "json".into()),
+                mockito::Matcher::UrlEncoded("maxwidth".into(), "640".into()),
+                mockito::Matcher::UrlEncoded("maxheight".into(), "360".into()),
+            ]))
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(
+                r#"
+                {
+                    "type": "video",
+                    "version": "1.0",
+                    "title": "Test Video",
+                    "author_name": "Tester",
+                    "provider_name": "TestTube",
+                    "thumbnail_url": "https://example.com/thumb.jpg",
+                    "thumbnail_width": 480,
+                    "thumbnail_height": "360",
+                    "html": "<iframe src=\"https://example.com/embed\"></iframe>",
+                    "width": 640,
+                    "height": 360
+                }
+            "#,
+            )
+            .create_async()
+            .await;
+
+        let url_path = UrlPath {
+            url: format!("{}/watch", url),
+        };
+        let options = ExtractOptions {
+            maxwidth: Some(640),
+            maxheight: Some(360),
+            ..Default::default()
+        };
+        let result = handle_extract(
+            test_state(),
+            ValidatedPath(url_path),
+            ValidatedQuery(options),
+            HeaderMap::new(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(result.status(), StatusCode::OK);
+
+        let body = body::to_bytes(result.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let page_info: PageInfo = serde_json::from_slice(&body).unwrap();
+
+        let oembed = page_info.oembed.unwrap();
+        assert_eq!(oembed.oembed_type, Some("video".to_string()));
+        assert_eq!(oembed.author_name, Some("Tester".to_string()));
+        assert_eq!(oembed.provider_name, Some("TestTube".to_string()));
+        assert_eq!(
+            oembed.html,
+            Some(r#"<iframe src="https://example.com/embed"></iframe>"#.to_string())
+        );
+        assert_eq!(oembed.thumbnail_width, Some(480));
+        assert_eq!(oembed.thumbnail_height, Some(360));
+        assert_eq!(oembed.width, Some(640));
+        assert_eq!(oembed.height, Some(360));
+    }
+
+    #[tokio::test]
+    async fn test_handle_extract_oembed_xml() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let _m = server
+            .mock("GET", "/")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body(
+                r#"
+                <html>
+                <head>
+                    <link rel="alternate" type="text/xml+oembed" href="/oembed.xml">
+                </head>
+                <body></body>
+                </html>
+            "#,
+            )
+            .create_async()
+            .await;
+
+        let _oembed_mock = server
+            .mock("GET", "/oembed.xml")
+            .with_status(200)
+            .with_header("content-type", "text/xml")
+            .with_body(
+                r#"<?xml version="1.0" encoding="utf-8"?>
+                <oembed>
+                    <type>rich</type>
+                    <provider_name>Example</provider_name>
+                    <html>&lt;div&gt;embed&lt;/div&gt;</html>
+                    <width>500</width>
+                </oembed>
+            "#,
+            )
+            .create_async()
+            .await;
+
+        let url_path = UrlPath { url: url.clone() };
+        let result = handle_extract(
+            test_state(),
+            ValidatedPath(url_path),
+            ValidatedQuery(ExtractOptions::default()),
+            HeaderMap::new(),
+        )
+        .await
+        .unwrap();
+
+        let body = body::to_bytes(result.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let page_info: PageInfo = serde_json::from_slice(&body).unwrap();
+
+        let oembed = page_info.oembed.unwrap();
+        assert_eq!(oembed.oembed_type, Some("rich".to_string()));
+        assert_eq!(oembed.provider_name, Some("Example".to_string()));
+        assert_eq!(oembed.html, Some("<div>embed</div>".to_string()));
+        assert_eq!(oembed.width, Some(500));
+    }
+
+    #[tokio::test]
+    async fn test_handle_extract_oembed_provider_registry() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let _m = server
+            .mock("GET", "/videos/42")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body("<html><head><title>Video</title></head></html>")
+            .create_async()
+            .await;
+
+        let page_url = format!("{}/videos/42", url);
+        let _oembed_mock = server
+            .mock("GET", "/api/oembed.json")
+            .match_query(mockito::Matcher::AllOf(vec![
+                mockito::Matcher::UrlEncoded("url".into(), page_url.clone()),
+                mockito::Matcher::UrlEncoded("format".into(), "json".into()),
+            ]))
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"type": "video", "provider_name": "Registry"}"#)
+            .create_async()
+            .await;
+
+        let registry_path =
+            std::env::temp_dir().join(format!("pages-meta-providers-{}.json", std::process::id()));
+        std::fs::write(
+            &registry_path,
+            serde_json::json!([
+                {
+                    "provider_name": "Registry",
+                    "provider_url": url,
+                    "endpoints": [
+                        {
+                            "schemes": [format!("{}/videos/*", url)],
+                            "url": format!("{}/api/oembed.{{format}}", url)
+                        }
+                    ]
+                }
+            ])
+            .to_string(),
+        )
+        .unwrap();
+        let oembed_providers = ProviderRegistry::load(&registry_path).unwrap();
+        std::fs::remove_file(&registry_path).unwrap();
+
+        let mut state = test_app_state();
+        state.oembed_providers = oembed_providers;
+        let state = Arc::new(state);
+        let url_path = UrlPath { url: page_url };
+        let result = handle_extract(
+            State(state),
+            ValidatedPath(url_path),
+            ValidatedQuery(ExtractOptions::default()),
+            HeaderMap::new(),
+        )
+        .await
+        .unwrap();
+
+        let body = body::to_bytes(result.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let page_info: PageInfo = serde_json::from_slice(&body).unwrap();
+
+        let oembed = page_info.oembed.unwrap();
+        assert_eq!(oembed.oembed_type, Some("video".to_string()));
+        assert_eq!(oembed.provider_name, Some("Registry".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_handle_extract_detects_encoding() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let (shift_jis, _, _) = encoding_rs::SHIFT_JIS.encode(
+            r#"<html><head><meta charset="Shift_JIS"><title>日本語のページ</title></head></html>"#,
+        );
+        let _shift_jis_mock = server
+            .mock("GET", "/sjis")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body(shift_jis)
+            .create_async()
+            .await;
+
+        let (windows_1251, _, _) = encoding_rs::WINDOWS_1251
+            .encode("<html><head><title>Привет, мир</title></head></html>");
+        let _windows_1251_mock = server
+            .mock("GET", "/cp1251")
+            .with_status(200)
+            .with_header("content-type", "text/html; charset=windows-1251")
+            .with_body(windows_1251)
+            .create_async()
+            .await;
+
+        for (path, title, encoding) in [
+            ("/sjis", "日本語のページ", "Shift_JIS"),
+            ("/cp1251", "Привет, мир", "windows-1251"),
+        ] {
+            let url_path = UrlPath {
+                url: format!("{}{}", url, path),
+            };
+            let result = handle_extract(
+                test_state(),
+                ValidatedPath(url_path),
+                ValidatedQuery(ExtractOptions::default()),
+                HeaderMap::new(),
+            )
+            .await
+            .unwrap();
+
+            let body = body::to_bytes(result.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            let page_info: PageInfo = serde_json::from_slice(&body).unwrap();
+
+            assert_eq!(page_info.title, Some(title.to_string()));
+            assert_eq!(page_info.encoding, Some(encoding.to_string()));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_handle_extract_blocks_private_addresses() {
+        for url in [
+            "http://127.0.0.1:1/",
+            "http://169.254.169.254/latest/meta-data/",
+            "http://[::1]/",
+            "http://localhost:1/",
+        ] {
+            let url_path = UrlPath {
+                url: url.to_string(),
+            };
+            let result = handle_extract(
+                State(Arc::default()),
+                ValidatedPath(url_path),
+                ValidatedQuery(ExtractOptions::default()),
+                HeaderMap::new(),
+            )
+            .await;
+
+            match result {
+                Err(err @ ServerError::ForbiddenAddress(_)) => {
+                    assert_eq!(err.into_response().status(), StatusCode::FORBIDDEN)
+                }
+                other => panic!("Expected ForbiddenAddress for {}, got {:?}", url, other),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_handle_extract_blocks_redirect_to_private_address() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let _m = server
+            .mock("GET", "/")
+            .with_status(302)
+            .with_header("location", "http://169.254.169.254/latest/meta-data/")
+            .create_async()
+            .await;
+
+        let url_path = UrlPath { url: url.clone() };
+        let result = handle_extract(
+            test_state(),
+            ValidatedPath(url_path),
+            ValidatedQuery(ExtractOptions::default()),
+            HeaderMap::new(),
+        )
+        .await;
+
+        assert!(matches!(result, Err(ServerError::ForbiddenAddress(_))));
+    }
+
+    #[tokio::test]
+    async fn test_handle_extract_blocks_private_names_behind_proxy() {
+        // Nothing listens on the proxy; the guard must refuse before it is used.
+        let state = AppState::new(
+            AddressGuard::default(),
+            HostPolicyStore::default(),
+            &HttpClientConfig {
+                proxy: Some("http://127.0.0.1:1".to_string()),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let url_path = UrlPath {
+            url: "http://localhost/".to_string(),
+        };
+        let result = handle_extract(
+            State(Arc::new(state)),
+            ValidatedPath(url_path),
+            ValidatedQuery(ExtractOptions::default()),
+            HeaderMap::new(),
+        )
+        .await;
+
+        assert!(matches!(result, Err(ServerError::ForbiddenAddress(_))));
+    }
+
+    #[tokio::test]
+    async fn test_handle_extract_host_policy() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let _m = server
+            .mock("GET", "/")
+            .with_status(302)
+            .with_header("location", "http://denied.localhost/")
+            .create_async()
+            .await;
+
+        let policy_path = std::env::temp_dir().join(format!(
+            "pages-meta-host-policy-{}.json",
+            std::process::id()
+        ));
+        std::fs::write(
+            &policy_path,
+            r#"{"allow": ["127.0.0.1", ".localhost"], "deny": ["denied.localhost"]}"#,
+        )
+        .unwrap();
+        let host_policy = HostPolicyStore::load(&policy_path).unwrap();
+        std::fs::remove_file(&policy_path).unwrap();
+
+        let state = Arc::new(
+            AppState::new(loopback_guard(), host_policy, &HttpClientConfig::default()).unwrap(),
+        );
+
+        for (url, host) in [
+            ("http://example.com/".to_string(), "example.com"),
+            (url.clone(), "denied.localhost"),
+        ] {
+            let url_path = UrlPath { url };
+            let result = handle_extract(
+                State(state.clone()),
+                ValidatedPath(url_path),
+                ValidatedQuery(ExtractOptions::default()),
+                HeaderMap::new(),
+            )
+            .await;
+
+            match result {
+                Err(ServerError::HostNotAllowed(err)) => assert_eq!(err.0, host),
+                other => panic!("Expected HostNotAllowed for {}, got {:?}", host, other),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_handle_extract_host_policy_skips_cache() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let _m = server
+            .mock("GET", "/")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body("<html><head><title>Page</title></head></html>")
+            .create_async()
+            .await;
+        server
+            .mock("HEAD", "/favicon.ico")
+            .with_status(404)
+            .create_async()
+            .await;
+
+        let policy_path = std::env::temp_dir().join(format!(
+            "pages-meta-host-policy-cache-{}.json",
+            std::process::id()
+        ));
+        std::fs::write(&policy_path, r#"{"allow": ["127.0.0.1"]}"#).unwrap();
+        let state = Arc::new(
+            AppState::new(
+                loopback_guard(),
+                HostPolicyStore::load(&policy_path).unwrap(),
+                &HttpClientConfig::default(),
+            )
+            .unwrap(),
+        );
+
+        let extract = || {
+            handle_extract(
+                State(state.clone()),
+                ValidatedPath(UrlPath { url: url.clone() }),
+                ValidatedQuery(ExtractOptions::default()),
+                HeaderMap::new(),
+            )
+        };
+        extract().await.unwrap();
+        assert_eq!(extract().await.unwrap().headers()["x-cache"], "HIT");
+
+        std::fs::write(&policy_path, r#"{"deny": ["127.0.0.1"]}"#).unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(&policy_path)
+            .unwrap()
+            .set_modified(std::time::SystemTime::now() + Duration::from_secs(60))
+            .unwrap();
+        assert!(state.host_policy.reload_if_changed().unwrap());
+        std::fs::remove_file(&policy_path).unwrap();
+
+        assert!(matches!(
+            extract().await,
+            Err(ServerError::HostNotAllowed(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_handle_extract_unexpected_not_modified() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let _m = server
+            .mock("GET", "/")
+            .with_status(304)
+            .create_async()
+            .await;
+
+        let result = handle_extract(
+            test_state(),
+            ValidatedPath(UrlPath { url }),
+            ValidatedQuery(ExtractOptions::default()),
+            HeaderMap::new(),
+        )
+        .await;
+
+        match result {
+            Err(err @ ServerError::UnexpectedNotModified) => {
+                assert_eq!(err.into_response().status(), StatusCode::BAD_GATEWAY)
+            }
+            other => panic!("Expected UnexpectedNotModified, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_handle_extract_stops_after_head() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let _m = server
+            .mock("GET", "/")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_chunked_body(|writer| {
+                writer.write_all(b"<html><head><title>Big Page</title></head><body>")?;
+                for _ in 0..1024 {
+                    writer.write_all(&[b'x'; 32 * 1024])?;
+                }
+                writer.write_all(b"</body></html>")
+            })
+            .create_async()
+            .await;
+
+        let mut state = test_app_state();
+        state.max_body_size = 2 * 1024 * 1024;
+        let state = Arc::new(state);
+
+        let url_path = UrlPath { url: url.clone() };
+        let result = handle_extract(
+            State(state.clone()),
+            ValidatedPath(url_path),
+            ValidatedQuery(ExtractOptions::default()),
+            HeaderMap::new(),
+        )
+        .await
+        .unwrap();
+
+        let body = body::to_bytes(result.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let page_info: PageInfo = serde_json::from_slice(&body).unwrap();
+        assert_eq!(page_info.title, Some("Big Page".to_string()));
+
+        let url_path = UrlPath { url };
+        let options = ExtractOptions {
+            body: true,
+            ..Default::default()
+        };
+        let result = handle_extract(
+            State(state),
+            ValidatedPath(url_path),
+            ValidatedQuery(options),
+            HeaderMap::new(),
+        )
+        .await;
+
+        match result {
+            Err(err @ ServerError::BodyTooLarge(_)) => {
+                assert_eq!(err.into_response().status(), StatusCode::PAYLOAD_TOO_LARGE)
+            }
+            other => panic!("Expected BodyTooLarge, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_handle_extract_rejects_non_html() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let _m = server
+            .mock("GET", "/image.iso")
+            .with_status(200)
+            .with_header("content-type", "application/octet-stream")
+            .with_body("not html")
+            .create_async()
+            .await;
+
+        let url_path = UrlPath {
+            url: format!("{}/image.iso", url),
+        };
+        let result = handle_extract(
+            test_state(),
+            ValidatedPath(url_path),
+            ValidatedQuery(ExtractOptions::default()),
+            HeaderMap::new(),
+        )
+        .await;
+
+        match result {
+            Err(err @ ServerError::UnsupportedContentType(_)) => {
+                assert_eq!(
+                    err.into_response().status(),
+                    StatusCode::UNSUPPORTED_MEDIA_TYPE
+                )
+            }
+            other => panic!("Expected UnsupportedContentType, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_handle_extract_http_client_config() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let _m = server
+            .mock("GET", "/")
+            .match_header("user-agent", "test-agent/1.0")
+            .match_header("accept-language", "de")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body("<html><head><title>Hallo</title></head></html>")
+            .create_async()
+            .await;
+
+        let mut default_headers = reqwest::header::HeaderMap::new();
+        default_headers.insert(
+            reqwest::header::ACCEPT_LANGUAGE,
+            reqwest::header::HeaderValue::from_static("de"),
+        );
+        let config = HttpClientConfig {
+            user_agent: "test-agent/1.0".to_string(),
+            default_headers,
+            ..Default::default()
+        };
+        let state = AppState::new(loopback_guard(), HostPolicyStore::default(), &config).unwrap();
+
+        let url_path = UrlPath { url };
+        let result = handle_extract(
+            State(Arc::new(state)),
+            ValidatedPath(url_path),
+            ValidatedQuery(ExtractOptions::default()),
+            HeaderMap::new(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(result.status(), StatusCode::OK);
+        let body = body::to_bytes(result.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let page_info: PageInfo = serde_json::from_slice(&body).unwrap();
+        assert_eq!(page_info.title, Some("Hallo".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_handle_extract_redirects() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let _start = server
+            .mock("GET", "/start")
+            .with_status(301)
+            .with_header("location", "/middle")
+            .create_async()
+            .await;
+        let _middle = server
+            .mock("GET", "/middle")
+            .with_status(302)
+            .with_header("location", &format!("{}/article", url))
+            .create_async()
+            .await;
+        let _article = server
+            .mock("GET", "/article")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body(
+                r#"
+                <html>
+                <head>
+                    <title>Article</title>
+                    <link rel="canonical" href="/articles/1">
+                </head>
+                </html>
+            "#,
+            )
+            .create_async()
+            .await;
+
+        let url_path = UrlPath {
+            url: format!("{}/start", url),
+        };
+        let result = handle_extract(
+            test_state(),
+            ValidatedPath(url_path),
+            ValidatedQuery(ExtractOptions::default()),
+            HeaderMap::new(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(result.status(), StatusCode::OK);
+        let body = body::to_bytes(result.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let page_info: PageInfo = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(page_info.final_url, Some(format!("{}/article", url)));
+        assert_eq!(page_info.canonical_url, Some(format!("{}/articles/1", url)));
+        let redirects = page_info.redirects.unwrap();
+        assert_eq!(redirects.len(), 2);
+        assert_eq!(redirects[0].url, format!("{}/start", url));
+        assert_eq!(redirects[0].status, 301);
+        assert_eq!(redirects[0].location, format!("{}/middle", url));
+        assert_eq!(redirects[1].status, 302);
+        assert_eq!(redirects[1].location, format!("{}/article", url));
+
+        for (max_redirects, stopped_at, location) in
+            [(0, "start", "middle"), (1, "middle", "article")]
+        {
+            let url_path = UrlPath {
+                url: format!("{}/start", url),
+            };
+            let options = ExtractOptions {
+                max_redirects: Some(max_redirects),
+                ..Default::default()
+            };
+            let result = handle_extract(
+                test_state(),
+                ValidatedPath(url_path),
+                ValidatedQuery(options),
+                HeaderMap::new(),
+            )
+            .await
+            .unwrap();
+
+            assert_eq!(result.status(), StatusCode::OK);
+            let body = body::to_bytes(result.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            let page_info: PageInfo = serde_json::from_slice(&body).unwrap();
+            assert_eq!(page_info.final_url, Some(format!("{}/{}", url, stopped_at)));
+            assert_eq!(page_info.redirects.unwrap().len(), max_redirects + 1);
+            let warnings = page_info.warnings.unwrap();
+            assert_eq!(warnings[0].resource, "redirect");
+            assert_eq!(warnings[0].url, format!("{}/{}", url, location));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_handle_extract_cache() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let fresh_mock = server
+            .mock("GET", "/fresh")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_header("cache-control", "max-age=60")
+            .with_body("<html><head><title>Fresh</title></head></html>")
+            .expect(1)
+            .create_async()
+            .await;
+        let stale_mock = server
+            .mock("GET", "/stale")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_header("cache-control", "max-age=0")
+            .with_body("<html><head><title>Stale</title></head></html>")
+            .expect(2)
+            .create_async()
+            .await;
+
+        let mut state = test_app_state();
+        state.cache = MetadataCache::new(
+            Box::new(MemoryStore::new(10)),
+            CacheConfig {
+                min_ttl: Duration::ZERO,
+                max_ttl: Duration::from_secs(60),
+                stale_ttl: Duration::from_secs(60),
+            },
+        );
+        let state = Arc::new(state);
+
+        for (path, title, cache_statuses) in [
+            ("/fresh", "Fresh", ["MISS", "HIT"]),
+            ("/stale", "Stale", ["MISS", "STALE"]),
+        ] {
+            for cache_status in cache_statuses {
+                let url_path = UrlPath {
+                    url: format!("{}{}", url, path),
+                };
+                let result = handle_extract(
+                    State(state.clone()),
+                    ValidatedPath(url_path),
+                    ValidatedQuery(ExtractOptions::default()),
+                    HeaderMap::new(),
+                )
+                .await
+                .unwrap();
+
+                assert_eq!(result.headers()[X_CACHE], cache_status);
+                let body = body::to_bytes(result.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                let page_info: PageInfo = serde_json::from_slice(&body).unwrap();
+                assert_eq!(page_info.title, Some(title.to_string()));
+            }
+        }
+
+        // The stale entry is refreshed in the background.
+        for _ in 0..100 {
+            if stale_mock.matched_async().await {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        fresh_mock.assert_async().await;
+        stale_mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_handle_extract_coalesces_concurrent_requests() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let page_mock = server
+            .mock("GET", "/page")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body("<html><head><title>Shared</title></head></html>")
+            .expect(1)
+            .create_async()
+            .await;
+        let image_mock = server
+            .mock("GET", "/image")
+            .with_status(200)
+            .with_header("content-type", "image/png")
+            .expect(1)
+            .create_async()
+            .await;
+
+        // Without a cache, only coalescing prevents repeated fetches.
+        let mut state = test_app_state();
+        state.cache = MetadataCache::new(Box::new(MemoryStore::new(0)), CacheConfig::default());
+        let state = Arc::new(state);
+        let extract = |path: &str| {
+            let url_path = UrlPath {
+                url: format!("{}{}", url, path),
+            };
+            handle_extract(
+                State(state.clone()),
+                ValidatedPath(url_path),
+                ValidatedQuery(ExtractOptions::default()),
+                HeaderMap::new(),
+            )
+        };
+
+        let results = tokio::join!(extract("/page"), extract("/page"), extract("/page"));
+        for result in [results.0, results.1, results.2] {
+            let result = result.unwrap();
+            assert_eq!(result.status(), StatusCode::OK);
+            let body = body::to_bytes(result.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            let page_info: PageInfo = serde_json::from_slice(&body).unwrap();
+            assert_eq!(page_info.title, Some("Shared".to_string()));
+        }
+
+        // Every waiting request receives the error.
+        let results = tokio::join!(extract("/image"), extract("/image"), extract("/image"));
+        for result in [results.0, results.1, results.2] {
+            let response = result.unwrap_err().into_response();
+            assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        }
+
+        page_mock.assert_async().await;
+        image_mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_handle_extract_revalidation() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let page_mock = server
+            .mock("GET", "/page")
+            .match_header("if-none-match", mockito::Matcher::Missing)
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_header("cache-control", "max-age=0")
+            .with_header("etag", "\"v1\"")
+            .with_body("<html><head><title>Page</title></head></html>")
+            .expect(1)
+            .create_async()
+            .await;
+        let not_modified_mock = server
+            .mock("GET", "/page")
+            .match_header("if-none-match", "\"v1\"")
+            .with_status(304)
+            .with_header("cache-control", "max-age=60")
+            .expect(1)
+            .create_async()
+            .await;
+
+        let mut state = test_app_state();
+        state.cache = MetadataCache::new(
+            Box::new(MemoryStore::new(10)),
+            CacheConfig {
+                min_ttl: Duration::ZERO,
+                max_ttl: Duration::from_secs(60),
+                stale_ttl: Duration::from_secs(60),
+            },
+        );
+        let state = Arc::new(state);
+        let extract = |headers: HeaderMap| {
+            let url_path = UrlPath {
+                url: format!("{}/page", url),
+            };
+            handle_extract(
+                State(state.clone()),
+                ValidatedPath(url_path),
+                ValidatedQuery(ExtractOptions::default()),
+                headers,
+            )
+        };
+
+        let result = extract(HeaderMap::new()).await.unwrap();
+        assert_eq!(result.headers()[X_CACHE], "MISS");
+        let etag = result.headers()[header::ETAG].clone();
+        let body = body::to_bytes(result.into_body(), usize::MAX)
+            .await
+            .unwrap();
+
+        // The stale result is revalidated with the page's ETag in the
+        // background, and kept when the page is unchanged.
+        let result = extract(HeaderMap::new()).await.unwrap();
+        assert_eq!(result.headers()[X_CACHE], "STALE");
+        for _ in 0..100 {
+            if not_modified_mock.matched_async().await {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        let mut result = extract(HeaderMap::new()).await.unwrap();
+        for _ in 0..100 {
+            if result.headers()[X_CACHE] == "HIT" {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            result = extract(HeaderMap::new()).await.unwrap();
+        }
+        assert_eq!(result.headers()[X_CACHE], "HIT");
+        assert_eq!(result.headers()[header::ETAG], etag);
+        assert_eq!(
+            body::to_bytes(result.into_body(), usize::MAX)
+                .await
+                .unwrap(),
+            body
+        );
+
+        // Clients revalidate with our ETag.
+        let mut headers = HeaderMap::new();
+        headers.insert(header::IF_NONE_MATCH, etag.clone());
+        let result = extract(headers).await.unwrap();
+        assert_eq!(result.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(result.headers()[header::ETAG], etag);
+
+        page_mock.assert_async().await;
+        not_modified_mock.assert_async().await;
+    }
+
+    #[test]
+    fn test_etag_matches() {
+        let etag = etag(b"{}");
+        assert!(etag_matches(&etag, &etag));
+        assert!(etag_matches(&format!("\"other\", W/{}", etag), &etag));
+        assert!(etag_matches("*", &etag));
+        assert!(!etag_matches("\"other\"", &etag));
+    }
+
+    #[tokio::test]
+    async fn test_handle_batch() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let page_mock = server
+            .mock("GET", "/page")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body(r#"<html><head><title>Page</title><link rel="icon" href="/icon.png"></head></html>"#)
+            .expect(2)
+            .create_async()
+            .await;
+        server
+            .mock("GET", "/missing")
+            .with_status(404)
+            .create_async()
+            .await;
+
+        let items: Vec<BatchItem> = serde_json::from_value(serde_json::json!([
+            format!("{}/page", url),
+            { "url": format!("{}/page", url), "original_urls": true },
+            "ftp://example.com/",
+            format!("{}/missing", url),
+        ]))
+        .unwrap();
+        let Json(results) = handle_batch(test_state(), Json(items)).await.unwrap();
+
+        let statuses: Vec<u16> = results.iter().map(|result| result.status).collect();
+        assert_eq!(statuses, [200, 200, 400, 404]);
+        assert_eq!(results[0].url, format!("{}/page", url));
+        assert_eq!(results[0].result.as_ref().unwrap()["title"], "Page");
+        assert_eq!(results[0].cache.as_deref(), Some("MISS"));
+        assert!(results[0].result.as_ref().unwrap()["original_urls"].is_null());
+        assert_eq!(
+            results[1].result.as_ref().unwrap()["original_urls"]["icons.0.href"],
+            "/icon.png"
+        );
+        assert_eq!(results[2].error.as_ref().unwrap()["code"], 400);
+        assert!(results[2].result.is_none());
+        assert_eq!(results[3].error.as_ref().unwrap()["message"], "Not Found");
+        page_mock.assert_async().await;
+
+        let mut state = test_app_state();
+        state.batch = BatchLimiter::new(BatchConfig {
+            max_size: 1,
+            ..Default::default()
+        });
+        let items = vec![
+            BatchItem::Url(format!("{}/page", url)),
+            BatchItem::Url(format!("{}/page", url)),
+        ];
+        let result = handle_batch(State(Arc::new(state)), Json(items)).await;
+        assert!(matches!(result, Err(ServerError::BatchTooLarge(1))));
+    }
+
+    #[tokio::test]
+    async fn test_batch_limiter_is_shared() {
+        let limiter = BatchLimiter::new(BatchConfig {
+            parallelism: 2,
+            host_parallelism: 1,
+            ..Default::default()
+        });
+        let blocked = |host: &'static str| {
+            let limiter = &limiter;
+            async move {
+                tokio::time::timeout(Duration::from_millis(50), limiter.acquire(host))
+                    .await
+                    .is_err()
+            }
+        };
+
+        // As if two batches ran at once.
+        let first = limiter.acquire("a.example").await;
+        assert!(blocked("a.example").await);
+        let second = limiter.acquire("b.example").await;
+        assert!(blocked("c.example").await);
+
+        drop(first);
+        assert!(!blocked("a.example").await);
+        drop(second);
+    }
+
+    #[tokio::test]
+    async fn test_handle_batch_cache_hits_skip_limits() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let page_mock = server
+            .mock("GET", "/page")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_header("cache-control", "max-age=60")
+            .with_body("<html><head><title>Page</title></head></html>")
+            .expect(1)
+            .create_async()
+            .await;
+
+        let mut state = test_app_state();
+        state.batch = BatchLimiter::new(BatchConfig {
+            host_parallelism: 1,
+            ..Default::default()
+        });
+        let state = Arc::new(state);
+        let batch = || {
+            let items = vec![BatchItem::Url(format!("{}/page", url))];
+            handle_batch(State(state.clone()), Json(items))
+        };
+
+        let Json(results) = batch().await.unwrap();
+        assert_eq!(results[0].cache.as_deref(), Some("MISS"));
+
+        // With the host's only slot taken, the cached page is still served.
+        let host = Url::parse(&url).unwrap().host_str().unwrap().to_string();
+        let _permits = state.batch.acquire(&host).await;
+        let Json(results) = tokio::time::timeout(Duration::from_millis(200), batch())
+            .await
+            .expect("the cache hit waited for a slot")
+            .unwrap();
+        assert_eq!(results[0].cache.as_deref(), Some("HIT"));
+        page_mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_handle_batch_cancelled() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let _m = server
+            .mock("GET", "/slow")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_chunked_body(|writer| {
+                writer.write_all(b"<html><head>")?;
+                std::thread::sleep(Duration::from_secs(2));
+                writer.write_all(b"</head></html>")
+            })
+            .create_async()
+            .await;
+
+        let mut state = test_app_state();
+        state.batch = BatchLimiter::new(BatchConfig {
+            host_parallelism: 1,
+            ..Default::default()
+        });
+        let state = Arc::new(state);
+
+        let items = vec![BatchItem::Url(format!("{}/slow", url))];
+        let batch = handle_batch(State(state.clone()), Json(items));
+        assert!(tokio::time::timeout(Duration::from_millis(200), batch)
+            .await
+            .is_err());
+
+        // The dropped batch no longer holds the host's only slot.
+        let host = Url::parse(&url).unwrap().host_str().unwrap().to_string();
+        let _permits = tokio::time::timeout(Duration::from_millis(200), state.batch.acquire(&host))
+            .await
+            .expect("the cancelled extraction still holds its slot");
+    }
+
+    #[tokio::test]
+    async fn test_handle_jobs() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        server
+            .mock("GET", "/page")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body("<html><head><title>Page</title></head></html>")
+            .create_async()
+            .await;
+        let callback_mock = server
+            .mock("POST", "/callback")
+            .match_header("content-type", "application/json")
+            .match_header(
+                jobs::SIGNATURE_HEADER,
+                mockito::Matcher::Regex("^sha256=[0-9a-f]{64}$".to_string()),
+            )
+            .match_header(
+                jobs::JOB_ID_HEADER,
+                mockito::Matcher::Regex("^[0-9a-f-]{36}$".to_string()),
+            )
+            .match_body(mockito::Matcher::PartialJson(serde_json::json!({
+                "title": "Page"
+            })))
+            .with_status(204)
+            .expect(1)
+            .create_async()
+            .await;
+
+        let request = |callback_url: Option<String>| {
+            serde_json::from_value::<JobRequest>(serde_json::json!({
+                "url": format!("{}/page", url),
+                "callback_url": callback_url,
+                "body": true
+            }))
+            .unwrap()
+        };
+
+        // Callbacks can't be signed without a secret.
+        let result = handle_create_job(
+            test_state(),
+            Json(request(Some(format!("{}/callback", url)))),
+        )
+        .await;
+        assert!(matches!(result, Err(ServerError::WebhooksDisabled)));
+
+        let mut state = test_app_state();
+        state.jobs = JobQueue::new(JobConfig {
+            webhook_secret: Some("secret".to_string()),
+            ..Default::default()
+        });
+        let state = Arc::new(state);
+        jobs::spawn_workers(state.clone());
+
+        let response = handle_create_job(
+            State(state.clone()),
+            Json(request(Some(format!("{}/callback", url)))),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        let location = response.headers()[header::LOCATION].clone();
+        let body = body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let job: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let id = job["id"].as_str().unwrap().to_string();
+        assert_eq!(location, format!("/jobs/{}", id));
+        assert_eq!(job["state"], "queued");
+
+        let mut job = None;
+        for _ in 0..100 {
+            let Json(polled) = handle_get_job(State(state.clone()), Path(id.clone()))
+                .await
+                .unwrap();
+            if polled.state == JobState::Succeeded {
+                job = Some(polled);
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        let job = job.expect("job did not finish");
+        assert_eq!(job.status, Some(200));
+        assert_eq!(job.result.unwrap()["title"], "Page");
+
+        for _ in 0..100 {
+            if callback_mock.matched_async().await {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        callback_mock.assert_async().await;
+
+        let result = handle_get_job(State(state), Path("unknown".to_string())).await;
+        assert!(matches!(result, Err(ServerError::JobNotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn test_handle_jobs_timeout() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let _m = server
+            .mock("GET", "/slow")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_chunked_body(|writer| {
+                writer.write_all(b"<html><head>")?;
+                std::thread::sleep(Duration::from_secs(2));
+                writer.write_all(b"</head></html>")
+            })
+            .create_async()
+            .await;
+
+        let mut state = test_app_state();
+        state.jobs = JobQueue::new(JobConfig {
+            timeout: Duration::from_millis(100),
+            ..Default::default()
+        });
+        let state = Arc::new(state);
+        jobs::spawn_workers(state.clone());
+
+        let request = serde_json::from_value::<JobRequest>(serde_json::json!({
+            "url": format!("{}/slow", url)
+        }))
+        .unwrap();
+        let response = handle_create_job(State(state.clone()), Json(request))
+            .await
+            .unwrap();
+        let body = body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let job: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let id = job["id"].as_str().unwrap().to_string();
+
+        let mut job = None;
+        for _ in 0..50 {
+            let Json(polled) = handle_get_job(State(state.clone()), Path(id.clone()))
+                .await
+                .unwrap();
+            if polled.state == JobState::Failed {
+                job = Some(polled);
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        let job = job.expect("job did not time out");
+        assert_eq!(job.status, Some(504));
+        assert_eq!(job.error.unwrap()["code"], 504);
+    }
+
+    #[tokio::test]
+    async fn test_handle_extract_rejects_invalid_encoding() {
+        let url_path = UrlPath {
+            url: "https://example.com/%FF".to_string(),
+        };
+        let result = handle_extract(
+            test_state(),
+            ValidatedPath(url_path),
+            ValidatedQuery(ExtractOptions::default()),
+            HeaderMap::new(),
+        )
+        .await;
+        match result {
+            Err(err @ ServerError::InvalidUrlEncoding) => {
+                assert_eq!(err.into_response().status(), StatusCode::BAD_REQUEST)
+            }
+            result => panic!("unexpected result: {:?}", result.map(|r| r.status())),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_handle_extract_query() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        server
+            .mock("GET", "/page")
+            .match_query(mockito::Matcher::UrlEncoded("q".into(), "a b".into()))
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body(r#"<html><head><title>Query</title><link rel="icon" href="/icon.png"></head></html>"#)
+            .create_async()
+            .await;
+
+        let query = |query: &str| {
+            let uri = format!("/extract?{}", query);
+            async move {
+                let (mut parts, _) = axum::http::Request::builder()
+                    .uri(uri)
+                    .body(())
+                    .unwrap()
+                    .into_parts();
+                let url_query =
+                    ValidatedQuery::<UrlQuery>::from_request_parts(&mut parts, &()).await?;
+                let options =
+                    ValidatedQuery::<ExtractOptions>::from_request_parts(&mut parts, &()).await?;
+                Ok::<_, ServerError>((url_query, options))
+            }
+        };
+
+        let page_url = format!("{}/page?q=a%20b", url);
+        let (url_query, options) = query(&format!(
+            "url={}&original_urls=true",
+            percent_encoding::utf8_percent_encode(&page_url, percent_encoding::NON_ALPHANUMERIC)
+        ))
+        .await
+        .unwrap();
+        assert_eq!(url_query.0.url, page_url);
+
+        let result = handle_extract_query(test_state(), url_query, options, HeaderMap::new())
+            .await
+            .unwrap();
+        assert_eq!(result.status(), StatusCode::OK);
+        let body = body::to_bytes(result.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let page_info: PageInfo = serde_json::from_slice(&body).unwrap();
+        assert_eq!(page_info.title, Some("Query".to_string()));
+        assert_eq!(
+            page_info
+                .original_urls
+                .unwrap()
+                .get("icons.0.href")
+                .unwrap(),
+            "/icon.png"
+        );
+
+        assert!(matches!(
+            query("url=ftp://example.com/").await,
+            Err(ServerError::ValidationError(_))
+        ));
+        assert!(matches!(
+            query("original_urls=true").await,
+            Err(ServerError::AxumQueryRejection(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_invalid_option_is_json_error() {
+        for uri in [
+            "/extract?url=https%3A%2F%2Fexample.com%2F&icon_size=abc",
+            "/https://example.com/?body=yes",
+        ] {
+            let (mut parts, _) = axum::http::Request::builder()
+                .uri(uri)
+                .body(())
+                .unwrap()
+                .into_parts();
+            let err = ValidatedQuery::<ExtractOptions>::from_request_parts(&mut parts, &())
+                .await
+                .unwrap_err();
+
+            let response = err.into_response();
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+            assert_eq!(response.headers()[header::CONTENT_TYPE], "application/json");
+            let body = body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            let error: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            assert!(error["error"]
+                .as_str()
+                .unwrap()
+                .contains("Failed to deserialize"));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_handle_extract_fields() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        server
+            .mock("GET", "/page")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body(format!(
+                r#"<html><head>
+                <title>Title</title>
+                <meta name="description" content="Description">
+                <meta property="og:title" content="OG title">
+                <meta property="og:image" content="/image.png">
+                <link rel="manifest" href="/manifest.json">
+                <link rel="alternate" type="application/json+oembed" href="{}/oembed">
+                </head></html>"#,
+                url
+            ))
+            .create_async()
+            .await;
+        let manifest_mock = server
+            .mock("GET", "/manifest.json")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"name": "App", "icons": [{"src": "/icon.png", "sizes": "192x192"}]}"#)
+            .expect(1)
+            .create_async()
+            .await;
+        let oembed_mock = server.mock("GET", "/oembed").expect(0).create_async().await;
+        let favicon_mock = server
+            .mock("HEAD", "/favicon.ico")
+            .expect(0)
+            .create_async()
+            .await;
+
+        let extract = |fields: &str| {
+            let url_path = UrlPath {
+                url: format!("{}/page", url),
+            };
+            let options: ExtractOptions =
+                serde_json::from_value(serde_json::json!({ "fields": fields })).unwrap();
+            handle_extract(
+                test_state(),
+                ValidatedPath(url_path),
+                ValidatedQuery(options),
+                HeaderMap::new(),
+            )
+        };
+
+        let result = extract("title,og.image").await.unwrap();
+        let body = body::to_bytes(result.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let page_info: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            page_info,
+            serde_json::json!({
+                "title": "Title",
+                "og_tags": { "image": [{ "url": format!("{}/image.png", url) }] }
+            })
+        );
+
+        // Only the manifest is needed for its icons.
+        let result = extract("manifest.icons").await.unwrap();
+        let body = body::to_bytes(result.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let page_info: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            page_info["manifest_info"]["icons"][0]["src"],
+            format!("{}/icon.png", url)
+        );
+        assert_eq!(page_info.as_object().unwrap().len(), 1);
+
+        manifest_mock.assert_async().await;
+        oembed_mock.assert_async().await;
+        favicon_mock.assert_async().await;
+
+        // Without any path, nothing is filtered.
+        for query in ["fields=", "fields=%2C%20"] {
+            let (mut parts, _) = axum::http::Request::builder()
+                .uri(format!("/extract?{}", query))
+                .body(())
+                .unwrap()
+                .into_parts();
+            let ValidatedQuery(options) =
+                ValidatedQuery::<ExtractOptions>::from_request_parts(&mut parts, &())
+                    .await
+                    .unwrap();
+            assert_eq!(options.fields, None);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_handle_extract_preview() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        server
+            .mock("GET", "/article")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body(
+                r##"<html><head>
+                <meta property="og:title" content="OG title">
+                <meta name="twitter:title" content="Twitter title">
+                <meta name="Author" content="Jane Doe">
+                <meta name="theme-color" content="#336699">
+                <meta property="article:published_time" content="2024-05-01T10:00:00Z">
+                <link rel="canonical" href="/canonical">
+                <link rel="manifest" href="/manifest.json">
+                <script type="application/ld+json">{
+                    "@type": "Article",
+                    "image": { "@type": "ImageObject", "name": "Cover photo", "url": "/cover.jpg" }
+                }</script>
+                <title>Title</title>
+                </head><body>
+                <h1>Heading</h1>
+                <img src="/small.png" width="10" height="10">
+                <img src="/large.png" width="800" height="600">
+                </body></html>"##,
+            )
+            .create_async()
+            .await;
+        server
+            .mock("GET", "/bare")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body(
+                r#"<html><body><h1> Just a <em>heading</em> </h1><img src="photo.png"></body></html>"#,
+            )
+            .create_async()
+            .await;
+        server
+            .mock("GET", "/manifest.json")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"name": "Example App", "description": "From the manifest"}"#)
+            .create_async()
+            .await;
+        server
+            .mock("HEAD", "/favicon.ico")
+            .with_status(404)
+            .create_async()
+            .await;
+
+        let extract = |path: &str| {
+            let url_path = UrlPath {
+                url: format!("{}{}", url, path),
+            };
+            let options = ExtractOptions {
+                body: true,
+                fields: Some(Fields::parse("preview")),
+                ..Default::default()
+            };
+            handle_extract(
+                test_state(),
+                ValidatedPath(url_path),
+                ValidatedQuery(options),
+                HeaderMap::new(),
+            )
+        };
+
+        let result = extract("/article").await.unwrap();
+        let body = body::to_bytes(result.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let page_info: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            page_info["preview"],
+            serde_json::json!({
+                "title": { "value": "OG title", "source": "og:title" },
+                "description": { "value": "From the manifest", "source": "manifest:description" },
+                "image": { "value": format!("{}/cover.jpg", url), "source": "json_ld:image" },
+                "site_name": { "value": "Example App", "source": "manifest:name" },
+                "type": { "value": "Article", "source": "json_ld:@type" },
+                "url": { "value": format!("{}/canonical", url), "source": "canonical_url" },
+                "author": { "value": "Jane Doe", "source": "meta:author" },
+                "published_time": {
+                    "value": "2024-05-01T10:00:00Z",
+                    "source": "meta:article:published_time"
+                },
+                "theme_color": { "value": "#336699", "source": "meta:theme-color" }
+            })
+        );
+
+        let result = extract("/bare").await.unwrap();
+        let body = body::to_bytes(result.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let page_info: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            page_info["preview"],
+            serde_json::json!({
+                "title": { "value": "Just a heading", "source": "h1" },
+                "image": { "value": format!("{}/photo.png", url), "source": "img" },
+                "url": { "value": format!("{}/bare", url), "source": "final_url" }
+            })
+        );
+
+        // Filtering changes nothing about the preview, although it skips
+        // the favicon probe.
+        let options = ExtractOptions {
+            body: true,
+            ..Default::default()
+        };
+        let result = handle_extract(
+            test_state(),
+            ValidatedPath(UrlPath {
+                url: format!("{}/bare", url),
+            }),
+            ValidatedQuery(options),
+            HeaderMap::new(),
+        )
+        .await
+        .unwrap();
+        let body = body::to_bytes(result.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let unfiltered: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(unfiltered["preview"], page_info["preview"]);
+    }
+
+    #[tokio::test]
+    async fn test_handle_extract_document_metadata() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        server
+            .mock("GET", "/posts/2")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body(
+                r##"<html lang="en-GB" dir="LTR"><head>
+                <meta name="keywords" content="rust, metadata,, html ">
+                <meta name="author" content="Jane Doe">
+                <meta name="author" content="Someone Else">
+                <meta name="robots" content="noindex, follow">
+                <meta name="generator" content="Hugo 0.120">
+                <meta name="theme-color" content="#336699" media="(prefers-color-scheme: light)">
+                <meta name="color-scheme" content="light dark">
+                <meta name="viewport" content="width=device-width, initial-scale=1">
+                <meta name="application-name" content="Blog">
+                <meta name="referrer" content="origin">
+                <meta http-equiv="refresh" content="30; url=/posts/3">
+                <link rel="amphtml" href="/amp/posts/2">
+                <link rel="prev" href="/posts/1">
+                <link rel="next" href="/posts/3">
+                <link rel="alternate" hreflang="de" href="/de/posts/2">
+                <link rel="alternate" hreflang="x-default" href="/posts/2">
+                <link rel="alternate" type="application/rss+xml" title="RSS" href="/feed.xml">
+                <link rel="alternate" type="application/atom+xml" href="/atom.xml">
+                </head></html>"##,
+            )
+            .create_async()
+            .await;
+        server
+            .mock("HEAD", "/favicon.ico")
+            .with_status(404)
+            .create_async()
+            .await;
+
+        let url_path = UrlPath {
+            url: format!("{}/posts/2", url),
+        };
+        let result = handle_extract(
+            test_state(),
+            ValidatedPath(url_path),
+            ValidatedQuery(ExtractOptions::default()),
+            HeaderMap::new(),
+        )
+        .await
+        .unwrap();
+        let body = body::to_bytes(result.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let page_info: PageInfo = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(page_info.lang.as_deref(), Some("en-GB"));
+        assert_eq!(page_info.dir.as_deref(), Some("ltr"));
+        assert_eq!(
+            page_info.keywords,
+            Some(vec![
+                "rust".to_string(),
+                "metadata".to_string(),
+                "html".to_string()
+            ])
+        );
+        assert_eq!(page_info.author.as_deref(), Some("Jane Doe"));
+        assert_eq!(page_info.robots.as_deref(), Some("noindex, follow"));
+        assert_eq!(page_info.generator.as_deref(), Some("Hugo 0.120"));
+        assert_eq!(page_info.theme_color.as_deref(), Some("#336699"));
+        assert_eq!(page_info.color_scheme.as_deref(), Some("light dark"));
+        assert_eq!(
+            page_info.viewport.as_deref(),
+            Some("width=device-width, initial-scale=1")
+        );
+        assert_eq!(page_info.application_name.as_deref(), Some("Blog"));
+        assert_eq!(page_info.referrer.as_deref(), Some("origin"));
+        assert_eq!(
+            page_info.refresh,
+            Some(Refresh {
+                delay: 30,
+                url: Some(format!("{}/posts/3", url)),
+            })
+        );
+        assert_eq!(page_info.amphtml, Some(format!("{}/amp/posts/2", url)));
+        assert_eq!(page_info.prev, Some(format!("{}/posts/1", url)));
+        assert_eq!(page_info.next, Some(format!("{}/posts/3", url)));
+        assert_eq!(
+            page_info.alternate_languages,
+            Some(vec![
+                AlternateLanguage {
+                    hreflang: "de".to_string(),
+                    href: format!("{}/de/posts/2", url),
+                },
+                AlternateLanguage {
+                    hreflang: "x-default".to_string(),
+                    href: format!("{}/posts/2", url),
+                },
+            ])
+        );
+        assert_eq!(
+            page_info.feeds,
+            Some(vec![
+                Feed {
+                    href: format!("{}/feed.xml", url),
+                    mime_type: "application/rss+xml".to_string(),
+                    title: Some("RSS".to_string()),
+                },
+                Feed {
+                    href: format!("{}/atom.xml", url),
+                    mime_type: "application/atom+xml".to_string(),
+                    title: None,
+                },
+            ])
+        );
+    }
 }
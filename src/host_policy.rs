@@ -0,0 +1,239 @@
+use serde::Deserialize;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime};
+use thiserror::Error;
+use url::Url;
+
+/// The on-disk policy file:
+///
+/// ```json
+/// { "allow": ["example.com", "*.example.org", ".example.net"], "deny": ["ads.example.com"] }
+/// ```
+///
+/// `example.com` matches only that host, `*.example.org` matches its
+/// subdomains and `.example.net` matches the domain and its subdomains.
+/// Denied hosts are always rejected; when `allow` is not empty, only the
+/// hosts it matches may be contacted.
+#[derive(Debug, Default, Deserialize)]
+struct HostPolicyFile {
+    #[serde(default)]
+    allow: Vec<String>,
+    #[serde(default)]
+    deny: Vec<String>,
+}
+
+#[derive(Debug, PartialEq)]
+enum HostPattern {
+    Exact(String),
+    Subdomains(String),
+    DomainAndSubdomains(String),
+}
+
+impl HostPattern {
+    fn parse(pattern: &str) -> Result<Self, HostPolicyLoadError> {
+        let pattern = pattern.trim();
+        let (constructor, domain): (fn(String) -> HostPattern, &str) =
+            if let Some(domain) = pattern.strip_prefix("*.") {
+                (HostPattern::Subdomains, domain)
+            } else if let Some(domain) = pattern.strip_prefix('.') {
+                (HostPattern::DomainAndSubdomains, domain)
+            } else {
+                (HostPattern::Exact, pattern)
+            };
+        let domain = idna::domain_to_ascii(domain.trim_end_matches('.'))
+            .map_err(|_| HostPolicyLoadError::InvalidPattern(pattern.to_string()))?;
+        if domain.is_empty() {
+            return Err(HostPolicyLoadError::InvalidPattern(pattern.to_string()));
+        }
+        Ok(constructor(domain))
+    }
+
+    fn matches(&self, host: &str) -> bool {
+        let is_subdomain = |domain: &str| {
+            host.strip_suffix(domain)
+                .is_some_and(|prefix| prefix.ends_with('.'))
+        };
+        match self {
+            HostPattern::Exact(domain) => host == domain,
+            HostPattern::Subdomains(domain) => is_subdomain(domain),
+            HostPattern::DomainAndSubdomains(domain) => host == domain || is_subdomain(domain),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct HostPolicy {
+    allow: Vec<HostPattern>,
+    deny: Vec<HostPattern>,
+}
+
+impl HostPolicy {
+    fn from_file(file: HostPolicyFile) -> Result<Self, HostPolicyLoadError> {
+        let parse = |patterns: Vec<String>| {
+            patterns
+                .iter()
+                .map(|pattern| HostPattern::parse(pattern))
+                .collect::<Result<Vec<_>, _>>()
+        };
+        Ok(HostPolicy {
+            allow: parse(file.allow)?,
+            deny: parse(file.deny)?,
+        })
+    }
+
+    pub fn is_allowed(&self, host: &str) -> bool {
+        let host = host.trim_end_matches('.').to_ascii_lowercase();
+        if self.deny.iter().any(|pattern| pattern.matches(&host)) {
+            return false;
+        }
+        self.allow.is_empty() || self.allow.iter().any(|pattern| pattern.matches(&host))
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum HostPolicyLoadError {
+    #[error(transparent)]
+    IOError(#[from] io::Error),
+
+    #[error(transparent)]
+    JSONError(#[from] serde_json::Error),
+
+    #[error("invalid host pattern: {0}")]
+    InvalidPattern(String),
+}
+
+#[derive(Debug, Clone, Error)]
+#[error("host {0} is not allowed by policy")]
+pub struct HostNotAllowedError(pub String);
+
+/// Holds the current [`HostPolicy`] and reloads it when its file changes.
+#[derive(Debug, Default)]
+pub struct HostPolicyStore {
+    path: Option<PathBuf>,
+    policy: RwLock<Arc<HostPolicy>>,
+    modified: Mutex<Option<SystemTime>>,
+}
+
+impl HostPolicyStore {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, HostPolicyLoadError> {
+        let store = HostPolicyStore {
+            path: Some(path.as_ref().to_path_buf()),
+            ..Default::default()
+        };
+        store.reload_if_changed()?;
+        Ok(store)
+    }
+
+    pub fn check(&self, url: &Url) -> Result<(), HostNotAllowedError> {
+        let host = url.host_str().unwrap_or_default();
+        let policy = self.policy.read().unwrap().clone();
+        if policy.is_allowed(host) {
+            Ok(())
+        } else {
+            Err(HostNotAllowedError(host.to_string()))
+        }
+    }
+
+    /// Re-reads the policy file if its modification time changed. On error
+    /// the previous policy stays in effect.
+    pub fn reload_if_changed(&self) -> Result<bool, HostPolicyLoadError> {
+        let Some(path) = &self.path else {
+            return Ok(false);
+        };
+        let modified = fs::metadata(path)?.modified()?;
+        if *self.modified.lock().unwrap() == Some(modified) {
+            return Ok(false);
+        }
+
+        let file: HostPolicyFile = serde_json::from_slice(&fs::read(path)?)?;
+        let policy = HostPolicy::from_file(file)?;
+        *self.policy.write().unwrap() = Arc::new(policy);
+        *self.modified.lock().unwrap() = Some(modified);
+        Ok(true)
+    }
+
+    /// Periodically reloads the policy file until the server stops.
+    pub async fn watch(self: Arc<Self>, period: Duration) {
+        let mut interval = tokio::time::interval(period);
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            match self.reload_if_changed() {
+                Ok(true) => tracing::info!("reloaded host policy from {:?}", self.path),
+                Ok(false) => {}
+                Err(err) => tracing::warn!("failed to reload host policy: {}", err),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(allow: &[&str], deny: &[&str]) -> HostPolicy {
+        HostPolicy::from_file(HostPolicyFile {
+            allow: allow.iter().map(|pattern| pattern.to_string()).collect(),
+            deny: deny.iter().map(|pattern| pattern.to_string()).collect(),
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_host_policy() {
+        let policy = policy(
+            &["example.com", "*.example.org", ".example.net", "bücher.de"],
+            &["ads.example.net"],
+        );
+
+        assert!(policy.is_allowed("example.com"));
+        assert!(policy.is_allowed("EXAMPLE.com."));
+        assert!(!policy.is_allowed("www.example.com"));
+        assert!(policy.is_allowed("www.example.org"));
+        assert!(!policy.is_allowed("example.org"));
+        assert!(policy.is_allowed("example.net"));
+        assert!(policy.is_allowed("a.b.example.net"));
+        assert!(!policy.is_allowed("ads.example.net"));
+        assert!(!policy.is_allowed("badexample.net"));
+        assert!(policy.is_allowed("xn--bcher-kva.de"));
+        assert!(!policy.is_allowed("other.com"));
+    }
+
+    #[test]
+    fn test_host_policy_deny_only() {
+        let policy = policy(&[], &["*.tracker.com"]);
+
+        assert!(policy.is_allowed("example.com"));
+        assert!(policy.is_allowed("tracker.com"));
+        assert!(!policy.is_allowed("cdn.tracker.com"));
+    }
+
+    #[test]
+    fn test_host_policy_store_reload() {
+        let path =
+            std::env::temp_dir().join(format!("pages-meta-policy-{}.json", std::process::id()));
+        fs::write(&path, r#"{"deny": ["example.com"]}"#).unwrap();
+        let store = HostPolicyStore::load(&path).unwrap();
+        let url = Url::parse("https://example.com/").unwrap();
+        assert!(store.check(&url).is_err());
+
+        fs::write(&path, r#"{"deny": ["example.org"]}"#).unwrap();
+        let file = fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(SystemTime::now() + Duration::from_secs(60))
+            .unwrap();
+        assert!(store.reload_if_changed().unwrap());
+        assert!(store.check(&url).is_ok());
+
+        fs::write(&path, "not json").unwrap();
+        let file = fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(SystemTime::now() + Duration::from_secs(120))
+            .unwrap();
+        assert!(store.reload_if_changed().is_err());
+        assert!(store.check(&url).is_ok());
+
+        fs::remove_file(&path).unwrap();
+    }
+}
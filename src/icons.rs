@@ -0,0 +1,140 @@
+use serde::{Deserialize, Serialize};
+
+use crate::manifest::ManifestInfo;
+
+/// `rel` tokens of `<link>` elements that point to an icon.
+const ICON_RELS: [&str; 5] = [
+    "icon",
+    "apple-touch-icon",
+    "apple-touch-icon-precomposed",
+    "mask-icon",
+    "fluid-icon",
+];
+
+pub const TILE_IMAGE_REL: &str = "msapplication-tileimage";
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Icon {
+    pub href: String,
+    pub rel: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sizes: Option<String>,
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+}
+
+pub fn is_icon_rel(rel: &str) -> bool {
+    rel.split_ascii_whitespace()
+        .any(|token| ICON_RELS.iter().any(|rel| token.eq_ignore_ascii_case(rel)))
+}
+
+/// Converts the `icons` member of a processed web app manifest.
+pub fn from_manifest(manifest: &ManifestInfo) -> Vec<Icon> {
+    manifest
+        .icons
+        .iter()
+        .map(|icon| Icon {
+            href: icon.src.clone(),
+            rel: "manifest".to_string(),
+            sizes: icon.sizes.clone(),
+            mime_type: icon.mime_type.clone(),
+            color: None,
+        })
+        .collect()
+}
+
+/// Picks the icon that best fits a square of `target` pixels.
+///
+/// Preference order: an exact size match, a scalable icon (`sizes="any"` or
+/// SVG), the smallest icon larger than the target, the largest icon smaller
+/// than the target, and finally icons without declared sizes. Monochrome
+/// `mask-icon`s are only used when nothing else is available.
+pub fn best_icon(icons: &[Icon], target: u32) -> Option<&Icon> {
+    let candidates: Vec<&Icon> = icons
+        .iter()
+        .filter(|icon| icon.rel != "mask-icon")
+        .collect();
+    let candidates = if candidates.is_empty() {
+        icons.iter().collect()
+    } else {
+        candidates
+    };
+
+    candidates
+        .into_iter()
+        .enumerate()
+        .min_by_key(|(index, icon)| (fit(icon, target), *index))
+        .map(|(_, icon)| icon)
+}
+
+fn fit(icon: &Icon, target: u32) -> (u8, u32) {
+    let sizes = icon.sizes.as_deref().unwrap_or_default();
+    let scalable = sizes
+        .split_ascii_whitespace()
+        .any(|size| size.eq_ignore_ascii_case("any"))
+        || icon
+            .mime_type
+            .as_deref()
+            .is_some_and(|mime_type| mime_type.eq_ignore_ascii_case("image/svg+xml"));
+    let sizes: Vec<u32> = sizes
+        .split_ascii_whitespace()
+        .filter_map(|size| {
+            let (width, height) = size
+                .to_ascii_lowercase()
+                .split_once('x')
+                .map(|(w, h)| (w.parse::<u32>().ok(), h.parse::<u32>().ok()))?;
+            Some(width?.max(height?))
+        })
+        .collect();
+
+    if sizes.contains(&target) {
+        (0, 0)
+    } else if scalable {
+        (1, 0)
+    } else if let Some(larger) = sizes.iter().filter(|size| **size > target).min() {
+        (2, larger - target)
+    } else if let Some(smaller) = sizes.iter().max() {
+        (3, target - smaller)
+    } else {
+        (4, 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn icon(rel: &str, sizes: Option<&str>) -> Icon {
+        Icon {
+            href: format!("{}-{}", rel, sizes.unwrap_or("none")),
+            rel: rel.to_string(),
+            sizes: sizes.map(|sizes| sizes.to_string()),
+            mime_type: None,
+            color: None,
+        }
+    }
+
+    #[test]
+    fn test_best_icon() {
+        let icons = vec![
+            icon("mask-icon", None),
+            icon("icon", None),
+            icon("icon", Some("16x16 32x32")),
+            icon("apple-touch-icon", Some("180x180")),
+            icon("manifest", Some("512x512")),
+        ];
+
+        assert_eq!(best_icon(&icons, 32).unwrap().href, "icon-16x16 32x32");
+        assert_eq!(
+            best_icon(&icons, 64).unwrap().href,
+            "apple-touch-icon-180x180"
+        );
+        assert_eq!(best_icon(&icons, 192).unwrap().href, "manifest-512x512");
+        assert_eq!(best_icon(&icons, 1024).unwrap().href, "manifest-512x512");
+        assert_eq!(best_icon(&icons[..2], 32).unwrap().href, "icon-none");
+        assert_eq!(best_icon(&icons[..1], 32).unwrap().href, "mask-icon-none");
+        assert!(best_icon(&[], 32).is_none());
+    }
+}
@@ -0,0 +1,291 @@
+use axum::body::Bytes;
+use hmac::{Hmac, Mac};
+use reqwest::header::{self, HeaderMap, HeaderValue};
+use serde::Serialize;
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+use crate::{
+    errors::ServerError,
+    handlers::{self, BatchResult, ExtractOptions},
+    state::AppState,
+};
+
+/// Carries the HMAC-SHA256 of a callback body, as `sha256=<hex>`.
+pub const SIGNATURE_HEADER: &str = "x-signature-256";
+/// Carries the id of the job a callback reports on.
+pub const JOB_ID_HEADER: &str = "x-job-id";
+
+#[derive(Debug, Clone)]
+pub struct JobConfig {
+    /// Jobs extracted at the same time.
+    pub workers: usize,
+    /// Jobs waiting for a worker before new ones are refused.
+    pub queue_size: usize,
+    /// How long a job may run before it fails.
+    pub timeout: Duration,
+    /// How long a finished job can still be polled.
+    pub ttl: Duration,
+    /// Key callbacks are signed with. Callbacks are refused without one.
+    pub webhook_secret: Option<String>,
+}
+
+impl Default for JobConfig {
+    fn default() -> Self {
+        JobConfig {
+            workers: 4,
+            queue_size: 1000,
+            timeout: Duration::from_secs(60),
+            ttl: Duration::from_secs(60 * 60),
+            webhook_secret: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobState {
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+}
+
+/// An extraction run in the background. Once finished, it carries the same
+/// `status`, `result` and `error` as an entry of a batch.
+#[derive(Debug, Clone, Serialize)]
+pub struct Job {
+    pub id: String,
+    pub url: String,
+    pub state: JobState,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<serde_json::Value>,
+    #[serde(skip)]
+    options: ExtractOptions,
+    #[serde(skip)]
+    callback_url: Option<String>,
+    #[serde(skip)]
+    finished_at: Option<Instant>,
+}
+
+/// Jobs by id, and the queue of those waiting for a worker.
+#[derive(Debug)]
+pub struct JobQueue {
+    jobs: Mutex<HashMap<String, Job>>,
+    sender: mpsc::Sender<String>,
+    receiver: tokio::sync::Mutex<mpsc::Receiver<String>>,
+    config: JobConfig,
+}
+
+impl JobQueue {
+    pub fn new(config: JobConfig) -> Self {
+        let (sender, receiver) = mpsc::channel(config.queue_size.max(1));
+        JobQueue {
+            jobs: Mutex::default(),
+            sender,
+            receiver: tokio::sync::Mutex::new(receiver),
+            config,
+        }
+    }
+
+    pub fn webhook_secret(&self) -> Option<&str> {
+        self.config.webhook_secret.as_deref()
+    }
+
+    /// Queues an extraction of `url`.
+    pub fn submit(
+        &self,
+        url: String,
+        options: ExtractOptions,
+        callback_url: Option<String>,
+    ) -> Result<Job, ServerError> {
+        let job = Job {
+            id: Uuid::new_v4().to_string(),
+            url,
+            state: JobState::Queued,
+            status: None,
+            result: None,
+            error: None,
+            options,
+            callback_url,
+            finished_at: None,
+        };
+
+        let mut jobs = self.jobs.lock().unwrap();
+        self.prune(&mut jobs);
+        self.sender
+            .try_send(job.id.clone())
+            .map_err(|_| ServerError::JobQueueFull)?;
+        jobs.insert(job.id.clone(), job.clone());
+        Ok(job)
+    }
+
+    pub fn get(&self, id: &str) -> Option<Job> {
+        let mut jobs = self.jobs.lock().unwrap();
+        self.prune(&mut jobs);
+        jobs.get(id).cloned()
+    }
+
+    /// Drops finished jobs that have expired.
+    fn prune(&self, jobs: &mut HashMap<String, Job>) {
+        jobs.retain(|_, job| {
+            job.finished_at
+                .is_none_or(|finished_at| finished_at.elapsed() < self.config.ttl)
+        });
+    }
+
+    fn update(&self, id: &str, update: impl FnOnce(&mut Job)) -> Option<Job> {
+        let mut jobs = self.jobs.lock().unwrap();
+        let job = jobs.get_mut(id)?;
+        update(job);
+        Some(job.clone())
+    }
+}
+
+impl Default for JobQueue {
+    fn default() -> Self {
+        JobQueue::new(JobConfig::default())
+    }
+}
+
+/// Starts the workers that run queued jobs for as long as the server runs.
+pub fn spawn_workers(state: Arc<AppState>) {
+    for _ in 0..state.jobs.config.workers.max(1) {
+        let state = state.clone();
+        tokio::spawn(async move {
+            loop {
+                // Only the idle worker holding the lock waits for a job.
+                let Some(id) = state.jobs.receiver.lock().await.recv().await else {
+                    break;
+                };
+                run(&state, &id).await;
+            }
+        });
+    }
+}
+
+async fn run(state: &Arc<AppState>, id: &str) {
+    let Some(job) = state.jobs.update(id, |job| job.state = JobState::Running) else {
+        return;
+    };
+
+    let timeout = state.jobs.config.timeout;
+    let extraction = handlers::batch_extract(state, job.url.clone(), job.options.clone(), None);
+    let result = match tokio::time::timeout(timeout, extraction).await {
+        Ok(result) => result,
+        Err(_) => BatchResult::failed(job.url, &ServerError::JobTimedOut(timeout.as_secs())),
+    };
+    let Some(job) = state.jobs.update(id, |job| {
+        job.state = if result.error.is_none() {
+            JobState::Succeeded
+        } else {
+            JobState::Failed
+        };
+        job.status = Some(result.status);
+        job.result = result.result;
+        job.error = result.error;
+        job.finished_at = Some(Instant::now());
+    }) else {
+        return;
+    };
+
+    if let (Some(callback_url), Some(secret)) = (&job.callback_url, state.jobs.webhook_secret()) {
+        if let Err(err) = notify(state, callback_url, &job, secret).await {
+            tracing::warn!("failed to deliver job {} to {}: {}", id, callback_url, err);
+        }
+    }
+}
+
+/// Posts the result of the finished job to its callback URL, signed with
+/// `secret`: the page info, or the error `GET /*url` would have answered.
+async fn notify(
+    state: &AppState,
+    callback_url: &str,
+    job: &Job,
+    secret: &str,
+) -> Result<(), ServerError> {
+    let body = match &job.result {
+        Some(result) => serde_json::to_vec(result)?,
+        None => serde_json::to_vec(&serde_json::json!({ "error": job.error }))?,
+    };
+    let body = Bytes::from(body);
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
+    );
+    headers.insert(
+        JOB_ID_HEADER,
+        HeaderValue::try_from(&job.id).expect("job ids are valid headers"),
+    );
+    headers.insert(
+        SIGNATURE_HEADER,
+        HeaderValue::try_from(sign(secret, &body)).expect("signature is a valid header"),
+    );
+    state
+        .post(callback_url, headers, body)
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(body);
+    format!("sha256={:x}", mac.finalize().into_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sign() {
+        assert_eq!(
+            sign("key", b"The quick brown fox jumps over the lazy dog"),
+            "sha256=f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_job_queue_full() {
+        let queue = JobQueue::new(JobConfig {
+            queue_size: 1,
+            ..Default::default()
+        });
+        let job = queue
+            .submit("https://example.com/".to_string(), Default::default(), None)
+            .unwrap();
+        assert_eq!(queue.get(&job.id).unwrap().state, JobState::Queued);
+
+        let result = queue.submit("https://example.com/".to_string(), Default::default(), None);
+        assert!(matches!(result, Err(ServerError::JobQueueFull)));
+    }
+
+    #[tokio::test]
+    async fn test_job_expires() {
+        let queue = JobQueue::new(JobConfig {
+            ttl: Duration::ZERO,
+            ..Default::default()
+        });
+        let job = queue
+            .submit("https://example.com/".to_string(), Default::default(), None)
+            .unwrap();
+        assert!(queue.get(&job.id).is_some());
+
+        queue.update(&job.id, |job| {
+            job.state = JobState::Succeeded;
+            job.finished_at = Some(Instant::now());
+        });
+        assert!(queue.get(&job.id).is_none());
+    }
+}
@@ -0,0 +1,188 @@
+use serde_json::Value;
+
+/// Parses the contents of a `<script type="application/ld+json">` block.
+///
+/// Real-world blocks are often not valid JSON, so on failure the text is
+/// sanitized (HTML comments, CDATA markers, JS comments, trailing commas and
+/// raw control characters inside strings) before a second attempt. Top-level
+/// arrays and `@graph` containers are flattened into individual items.
+pub fn parse(text: &str) -> Vec<Value> {
+    let value = serde_json::from_str::<Value>(text)
+        .or_else(|_| serde_json::from_str::<Value>(&sanitize(text)));
+
+    let mut items = Vec::new();
+    if let Ok(value) = value {
+        flatten(value, None, &mut items);
+    }
+    items
+}
+
+fn flatten(value: Value, context: Option<&Value>, items: &mut Vec<Value>) {
+    match value {
+        Value::Array(array) => {
+            for item in array {
+                flatten(item, context, items);
+            }
+        }
+        Value::Object(mut object) => {
+            // A single-node `@graph` may be an object rather than an array.
+            if matches!(
+                object.get("@graph"),
+                Some(Value::Array(_) | Value::Object(_))
+            ) {
+                let graph = object.remove("@graph").unwrap_or_default();
+                let context = object.get("@context").or(context);
+                flatten(graph, context, items);
+            } else {
+                if let Some(context) = context {
+                    object.entry("@context").or_insert_with(|| context.clone());
+                }
+                items.push(Value::Object(object));
+            }
+        }
+        _ => {}
+    }
+}
+
+fn sanitize(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    let mut in_string = false;
+
+    while let Some(c) = rest.chars().next() {
+        if in_string {
+            match c {
+                '\\' => {
+                    let mut chars = rest.chars();
+                    result.push(chars.next().unwrap_or_default());
+                    if let Some(escaped) = chars.next() {
+                        result.push(escaped);
+                    }
+                    rest = chars.as_str();
+                    continue;
+                }
+                '"' => in_string = false,
+                '\n' => {
+                    result.push_str("\\n");
+                    rest = &rest[1..];
+                    continue;
+                }
+                '\r' => {
+                    result.push_str("\\r");
+                    rest = &rest[1..];
+                    continue;
+                }
+                '\t' => {
+                    result.push_str("\\t");
+                    rest = &rest[1..];
+                    continue;
+                }
+                _ => {}
+            }
+            result.push(c);
+            rest = &rest[c.len_utf8()..];
+            continue;
+        }
+
+        if let Some(marker) = ["<!--", "-->", "<![CDATA[", "]]>"]
+            .iter()
+            .find(|marker| rest.starts_with(**marker))
+        {
+            rest = &rest[marker.len()..];
+            continue;
+        }
+        if rest.starts_with("//") {
+            rest = rest.find('\n').map(|end| &rest[end..]).unwrap_or("");
+            continue;
+        }
+        if rest.starts_with("/*") {
+            rest = rest.find("*/").map(|end| &rest[end + 2..]).unwrap_or("");
+            continue;
+        }
+        if c == ',' {
+            let next = rest[1..].trim_start();
+            if next.starts_with('}') || next.starts_with(']') {
+                rest = &rest[1..];
+                continue;
+            }
+        }
+        if c == '"' {
+            in_string = true;
+        }
+        result.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_flattens_graph() {
+        let items = parse(
+            r#"{
+                "@context": "https://schema.org",
+                "@graph": [
+                    {"@type": "Organization", "name": "Example"},
+                    {"@type": "WebSite", "url": "https://example.com/"}
+                ]
+            }"#,
+        );
+
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0]["@type"], "Organization");
+        assert_eq!(items[0]["@context"], "https://schema.org");
+        assert_eq!(items[1]["@type"], "WebSite");
+    }
+
+    #[test]
+    fn test_parse_object_graph() {
+        let items = parse(
+            r#"{
+                "@context": "https://schema.org",
+                "@graph": {"@type": "Organization", "name": "Example"}
+            }"#,
+        );
+
+        assert_eq!(
+            items,
+            vec![json!({
+                "@context": "https://schema.org",
+                "@type": "Organization",
+                "name": "Example"
+            })]
+        );
+    }
+
+    #[test]
+    fn test_parse_malformed() {
+        let items = parse(
+            "<!--//--><![CDATA[//><!--
+            [{
+                \"@type\": \"Article\", // headline follows
+                \"headline\": \"Line one
+line two\",
+                \"keywords\": [\"a\", \"b\",],
+            }]
+            //--><!]]>",
+        );
+
+        assert_eq!(
+            items,
+            vec![json!({
+                "@type": "Article",
+                "headline": "Line one\nline two",
+                "keywords": ["a", "b"]
+            })]
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(parse("{not json").is_empty());
+    }
+}
@@ -0,0 +1,116 @@
+use serde::{Deserialize, Serialize};
+
+/// `type`s of `<link rel="alternate">` elements that point to a feed.
+const FEED_TYPES: [&str; 3] = [
+    "application/rss+xml",
+    "application/atom+xml",
+    "application/feed+json",
+];
+
+/// A translation of the page, from `<link rel="alternate" hreflang>`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct AlternateLanguage {
+    pub hreflang: String,
+    pub href: String,
+}
+
+/// An RSS, Atom or JSON feed, from `<link rel="alternate">`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Feed {
+    pub href: String,
+    #[serde(rename = "type")]
+    pub mime_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+}
+
+/// A `<meta http-equiv="refresh">` reload or redirect.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Refresh {
+    /// Seconds before the refresh.
+    pub delay: u64,
+    /// Where to go, or `None` to reload the page.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+}
+
+pub fn has_rel(rel: &str, token: &str) -> bool {
+    rel.split_ascii_whitespace()
+        .any(|rel| rel.eq_ignore_ascii_case(token))
+}
+
+pub fn is_feed_type(mime_type: &str) -> bool {
+    FEED_TYPES
+        .iter()
+        .any(|feed_type| mime_type.trim().eq_ignore_ascii_case(feed_type))
+}
+
+/// Parses the `content` of a refresh, such as `5` or `0; url='/next'`.
+pub fn parse_refresh(content: &str) -> Option<Refresh> {
+    let content = content.trim_start();
+    let digits = content
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(content.len());
+    let delay = content[..digits].parse().ok()?;
+
+    // Any fractional part of the delay is ignored.
+    let rest = content[digits..].trim_start_matches(|c: char| c.is_ascii_digit() || c == '.');
+    let rest = rest.trim_start();
+    let rest = match rest.strip_prefix([';', ',']) {
+        Some(rest) => rest.trim_start(),
+        None if rest.is_empty() => rest,
+        None => return None,
+    };
+    let url = match rest.get(..3) {
+        Some(prefix) if prefix.eq_ignore_ascii_case("url") => {
+            match rest[3..].trim_start().strip_prefix('=') {
+                Some(url) => url.trim_start(),
+                None => rest,
+            }
+        }
+        _ => rest,
+    };
+    let url = match url.chars().next() {
+        Some(quote @ ('\'' | '"')) => url[1..].split(quote).next().unwrap_or_default(),
+        _ => url,
+    };
+    let url = url.trim();
+
+    Some(Refresh {
+        delay,
+        url: (!url.is_empty()).then(|| url.to_string()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_refresh() {
+        let refresh = |delay, url: Option<&str>| {
+            Some(Refresh {
+                delay,
+                url: url.map(str::to_string),
+            })
+        };
+        assert_eq!(parse_refresh("30"), refresh(30, None));
+        assert_eq!(parse_refresh("0; url=/next"), refresh(0, Some("/next")));
+        assert_eq!(
+            parse_refresh("5.5 ,URL = 'https://example.com/a b' "),
+            refresh(5, Some("https://example.com/a b"))
+        );
+        assert_eq!(parse_refresh("1; /plain"), refresh(1, Some("/plain")));
+        assert_eq!(parse_refresh("soon"), None);
+        assert_eq!(parse_refresh("5 url=/next"), None);
+    }
+
+    #[test]
+    fn test_is_feed_type() {
+        assert!(is_feed_type("application/rss+xml"));
+        assert!(is_feed_type(" Application/Atom+XML"));
+        assert!(is_feed_type("application/feed+json"));
+        assert!(!is_feed_type("application/json"));
+        assert!(!is_feed_type("text/html"));
+    }
+}
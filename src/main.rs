@@ -1,14 +1,42 @@
+mod cache;
+mod document;
+mod encoding;
 mod errors;
 mod extractors;
+mod fields;
 mod handlers;
+mod host_policy;
+mod icons;
+mod jobs;
+mod json_ld;
+mod links;
+mod manifest;
+mod microdata;
 mod middlewares;
+mod oembed;
+mod preview;
+mod single_flight;
+mod sqlite_store;
+mod ssrf;
+mod state;
 
-use std::time::Duration;
+use std::{collections::HashMap, sync::Arc, time::Duration};
 
-use axum::{routing::get, Router};
+use axum::{
+    routing::{get, post},
+    Router,
+};
+use cache::{CacheConfig, CacheStore, MemoryStore, MetadataCache};
 use config::{Config, ConfigError};
+use host_policy::HostPolicyStore;
+use jobs::{JobConfig, JobQueue};
 use middlewares::timing_middleware;
+use oembed::ProviderRegistry;
+use reqwest::header::HeaderMap;
 use serde::Deserialize;
+use sqlite_store::SqliteStore;
+use ssrf::AddressGuard;
+use state::{AppState, BatchConfig, BatchLimiter, HttpClientConfig, HttpVersion};
 use tokio::{net::TcpListener, signal};
 use tower_http::{timeout::TimeoutLayer, trace::TraceLayer};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
@@ -17,6 +45,124 @@ use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 struct AppConfig {
     host: String,
     port: usize,
+    /// Path to an oEmbed provider registry in the oembed.com `providers.json` format.
+    oembed_providers: Option<String>,
+    /// Comma-separated ranges exempt from the private address guard.
+    ssrf_allowlist: Option<String>,
+    /// Path to a JSON file with `allow`/`deny` host lists, reloaded on change.
+    host_policy: Option<String>,
+    /// Maximum size in bytes of a page body, 5 MiB by default.
+    max_body_size: Option<usize>,
+    /// `User-Agent` of outgoing requests.
+    user_agent: Option<String>,
+    /// Connect timeout of outgoing requests, in seconds.
+    connect_timeout: Option<u64>,
+    /// Read timeout of outgoing requests, in seconds.
+    read_timeout: Option<u64>,
+    max_redirects: Option<usize>,
+    /// Proxy URL for all outgoing requests.
+    proxy: Option<String>,
+    /// `auto`, `http1` or `http2`.
+    http_version: Option<HttpVersion>,
+    /// JSON object of headers added to every outgoing request.
+    default_headers: Option<String>,
+    /// Maximum number of results in the in-memory cache, 0 to disable it.
+    cache_capacity: Option<usize>,
+    /// Path to an SQLite database used as a persistent cache instead.
+    cache_path: Option<String>,
+    /// Maximum size in bytes of the results in the persistent cache.
+    cache_max_size: Option<u64>,
+    /// Bounds in seconds of how long a result is cached.
+    cache_min_ttl: Option<u64>,
+    cache_max_ttl: Option<u64>,
+    /// Seconds an expired result may still be served while it is refreshed.
+    cache_stale_ttl: Option<u64>,
+    /// Most URLs accepted by `POST /batch`.
+    batch_max_size: Option<usize>,
+    /// Extractions of a batch running at the same time, overall and per host.
+    batch_parallelism: Option<usize>,
+    batch_host_parallelism: Option<usize>,
+    /// Background extraction workers.
+    job_workers: Option<usize>,
+    /// Jobs waiting for a worker before new ones are refused.
+    job_queue_size: Option<usize>,
+    /// Seconds a job may run before it fails.
+    job_timeout: Option<u64>,
+    /// Seconds a finished job can still be polled.
+    job_ttl: Option<u64>,
+    /// Key job callbacks are signed with.
+    webhook_secret: Option<String>,
+}
+
+const HOST_POLICY_RELOAD_INTERVAL: Duration = Duration::from_secs(5);
+
+fn cache_config(config: &AppConfig) -> CacheConfig {
+    let defaults = CacheConfig::default();
+    let seconds = |value: Option<u64>, default| value.map(Duration::from_secs).unwrap_or(default);
+    CacheConfig {
+        min_ttl: seconds(config.cache_min_ttl, defaults.min_ttl),
+        max_ttl: seconds(config.cache_max_ttl, defaults.max_ttl),
+        stale_ttl: seconds(config.cache_stale_ttl, defaults.stale_ttl),
+    }
+}
+
+fn batch_config(config: &AppConfig) -> BatchConfig {
+    let defaults = BatchConfig::default();
+    BatchConfig {
+        max_size: config.batch_max_size.unwrap_or(defaults.max_size),
+        parallelism: config
+            .batch_parallelism
+            .unwrap_or(defaults.parallelism)
+            .max(1),
+        host_parallelism: config
+            .batch_host_parallelism
+            .unwrap_or(defaults.host_parallelism)
+            .max(1),
+    }
+}
+
+fn job_config(config: &AppConfig) -> JobConfig {
+    let defaults = JobConfig::default();
+    JobConfig {
+        workers: config.job_workers.unwrap_or(defaults.workers),
+        queue_size: config.job_queue_size.unwrap_or(defaults.queue_size),
+        timeout: config
+            .job_timeout
+            .map(Duration::from_secs)
+            .unwrap_or(defaults.timeout),
+        ttl: config
+            .job_ttl
+            .map(Duration::from_secs)
+            .unwrap_or(defaults.ttl),
+        webhook_secret: config.webhook_secret.clone(),
+    }
+}
+
+fn http_client_config(config: &AppConfig) -> HttpClientConfig {
+    let defaults = HttpClientConfig::default();
+    let default_headers = match config.default_headers {
+        Some(ref headers) => {
+            let headers: HashMap<String, String> =
+                serde_json::from_str(headers).expect("invalid default headers");
+            HeaderMap::try_from(&headers).expect("invalid default headers")
+        }
+        None => defaults.default_headers,
+    };
+    HttpClientConfig {
+        user_agent: config.user_agent.clone().unwrap_or(defaults.user_agent),
+        connect_timeout: config
+            .connect_timeout
+            .map(Duration::from_secs)
+            .unwrap_or(defaults.connect_timeout),
+        read_timeout: config
+            .read_timeout
+            .map(Duration::from_secs)
+            .unwrap_or(defaults.read_timeout),
+        max_redirects: config.max_redirects.unwrap_or(defaults.max_redirects),
+        proxy: config.proxy.clone(),
+        http_version: config.http_version.unwrap_or(defaults.http_version),
+        default_headers,
+    }
 }
 
 fn load_config() -> Result<AppConfig, ConfigError> {
@@ -47,14 +193,70 @@ async fn main() {
     let config = load_config().unwrap();
     setup_tracing();
 
+    let oembed_providers = match config.oembed_providers {
+        Some(ref path) => ProviderRegistry::load(path).expect("failed to load oEmbed providers"),
+        None => ProviderRegistry::default(),
+    };
+    let allowlist = match config.ssrf_allowlist {
+        Some(ref allowlist) => {
+            AddressGuard::parse_allowlist(allowlist).expect("invalid SSRF allowlist")
+        }
+        None => Vec::new(),
+    };
+    let host_policy = match config.host_policy {
+        Some(ref path) => HostPolicyStore::load(path).expect("failed to load host policy"),
+        None => HostPolicyStore::default(),
+    };
+    let mut state = AppState::new(
+        AddressGuard::new(allowlist),
+        host_policy,
+        &http_client_config(&config),
+    )
+    .expect("failed to build HTTP client");
+    if let Some(ref proxy) = config.proxy {
+        tracing::warn!(
+            "requests go through the proxy {}, which resolves host names again after they \
+             were checked; a name whose addresses change in between can reach private addresses",
+            proxy
+        );
+    }
+    state.oembed_providers = oembed_providers;
+    let cache_store: Box<dyn CacheStore> = match config.cache_path {
+        Some(ref path) => Box::new(
+            SqliteStore::open(
+                path,
+                config
+                    .cache_max_size
+                    .unwrap_or(sqlite_store::DEFAULT_MAX_SIZE),
+            )
+            .expect("failed to open cache database"),
+        ),
+        None => Box::new(MemoryStore::new(
+            config.cache_capacity.unwrap_or(cache::DEFAULT_CAPACITY),
+        )),
+    };
+    state.cache = MetadataCache::new(cache_store, cache_config(&config));
+    state.max_body_size = config.max_body_size.unwrap_or(state::DEFAULT_MAX_BODY_SIZE);
+    state.batch = BatchLimiter::new(batch_config(&config));
+    state.jobs = JobQueue::new(job_config(&config));
+    let state = Arc::new(state);
+    jobs::spawn_workers(state.clone());
+    if config.host_policy.is_some() {
+        tokio::spawn(state.host_policy.clone().watch(HOST_POLICY_RELOAD_INTERVAL));
+    }
+
     let app = Router::new()
         .route("/*url", get(handlers::handle_extract))
+        .route("/extract", get(handlers::handle_extract_query))
         .route("/_healthz", get(handlers::handle_health))
+        .route("/jobs", post(handlers::handle_create_job))
+        .route("/jobs/:id", get(handlers::handle_get_job))
+        .layer(TimeoutLayer::new(Duration::from_secs(10)))
+        // A batch takes as long as its slowest extractions.
+        .route("/batch", post(handlers::handle_batch))
         .layer(axum::middleware::from_fn(timing_middleware))
-        .layer((
-            TraceLayer::new_for_http(),
-            TimeoutLayer::new(Duration::from_secs(10)),
-        ));
+        .layer(TraceLayer::new_for_http())
+        .with_state(state);
 
     let listener = TcpListener::bind((config.host, config.port as u16))
         .await
@@ -0,0 +1,172 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use url::Url;
+
+const DISPLAY_MODES: [&str; 4] = ["fullscreen", "standalone", "minimal-ui", "browser"];
+const DIRECTIONS: [&str; 3] = ["ltr", "rtl", "auto"];
+
+/// The members of a W3C Web App Manifest, processed leniently: members with
+/// an unexpected type are ignored and URLs are resolved against the manifest
+/// URL.
+#[derive(Debug, Serialize, Default, Deserialize)]
+pub struct ManifestInfo {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub short_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub theme_color: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub background_color: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lang: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dir: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub categories: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub icons: Vec<ManifestImage>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub screenshots: Vec<ManifestImage>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub shortcuts: Vec<ManifestShortcut>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ManifestImage {
+    pub src: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sizes: Option<String>,
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub purpose: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub form_factor: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ManifestShortcut {
+    pub name: String,
+    pub url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub short_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub icons: Vec<ManifestImage>,
+}
+
+/// Processes a manifest downloaded from `manifest_url` and linked from the
+/// page at `document_url`.
+pub fn parse(manifest: &Value, manifest_url: &Url, document_url: &Url) -> ManifestInfo {
+    let text = |name: &str| string_member(manifest, name);
+
+    // `start_url` must be same-origin with the document, otherwise the
+    // document URL is used instead.
+    let start_url = text("start_url")
+        .and_then(|start_url| manifest_url.join(&start_url).ok())
+        .filter(|start_url| start_url.origin() == document_url.origin())
+        .unwrap_or_else(|| document_url.clone());
+
+    // `scope` defaults to the directory of `start_url` and is ignored when it
+    // doesn't contain `start_url`.
+    let default_scope = start_url.join(".").unwrap_or_else(|_| start_url.clone());
+    let scope = text("scope")
+        .and_then(|scope| manifest_url.join(&scope).ok())
+        .filter(|scope| is_within_scope(&start_url, scope))
+        .unwrap_or(default_scope);
+
+    let shortcuts = manifest
+        .get("shortcuts")
+        .and_then(Value::as_array)
+        .map(|shortcuts| {
+            shortcuts
+                .iter()
+                .filter_map(|shortcut| {
+                    let url = manifest_url
+                        .join(&string_member(shortcut, "url")?)
+                        .ok()
+                        .filter(|url| is_within_scope(url, &scope))?;
+                    Some(ManifestShortcut {
+                        name: string_member(shortcut, "name")?,
+                        url: url.to_string(),
+                        short_name: string_member(shortcut, "short_name"),
+                        description: string_member(shortcut, "description"),
+                        icons: images(shortcut, "icons", manifest_url),
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    ManifestInfo {
+        name: text("name"),
+        short_name: text("short_name"),
+        description: text("description"),
+        start_url: Some(start_url.to_string()),
+        scope: Some(scope.to_string()),
+        display: text("display").filter(|display| DISPLAY_MODES.contains(&display.as_str())),
+        theme_color: text("theme_color"),
+        background_color: text("background_color"),
+        lang: text("lang"),
+        dir: text("dir").filter(|dir| DIRECTIONS.contains(&dir.as_str())),
+        categories: manifest
+            .get("categories")
+            .and_then(Value::as_array)
+            .map(|categories| {
+                categories
+                    .iter()
+                    .filter_map(Value::as_str)
+                    .map(|category| category.trim().to_lowercase())
+                    .collect()
+            })
+            .unwrap_or_default(),
+        icons: images(manifest, "icons", manifest_url),
+        screenshots: images(manifest, "screenshots", manifest_url),
+        shortcuts,
+    }
+}
+
+fn string_member(object: &Value, name: &str) -> Option<String> {
+    object
+        .get(name)
+        .and_then(Value::as_str)
+        .map(|value| value.trim().to_string())
+}
+
+fn images(object: &Value, name: &str, manifest_url: &Url) -> Vec<ManifestImage> {
+    let Some(images) = object.get(name).and_then(Value::as_array) else {
+        return Vec::new();
+    };
+    images
+        .iter()
+        .filter_map(|image| {
+            Some(ManifestImage {
+                src: manifest_url
+                    .join(&string_member(image, "src")?)
+                    .ok()?
+                    .to_string(),
+                sizes: string_member(image, "sizes"),
+                mime_type: string_member(image, "type"),
+                purpose: string_member(image, "purpose"),
+                label: string_member(image, "label"),
+                form_factor: string_member(image, "form_factor"),
+            })
+        })
+        .collect()
+}
+
+fn is_within_scope(url: &Url, scope: &Url) -> bool {
+    url.origin() == scope.origin() && url.path().starts_with(scope.path())
+}
@@ -0,0 +1,301 @@
+use markup5ever_rcdom::{Handle, NodeData};
+use serde_json::{Map, Value};
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+use url::Url;
+
+/// Extracts top-level HTML microdata items (`itemscope` elements without an
+/// `itemprop`) following the WHATWG microdata model, including `itemref`.
+/// URL-valued properties and `itemid` are resolved against `base`.
+pub fn extract_microdata(document: &Handle, base: &Url) -> Vec<Value> {
+    let mut ids = HashMap::new();
+    index_ids(document, &mut ids);
+
+    let mut roots = Vec::new();
+    find_microdata_roots(document, &mut roots);
+
+    roots
+        .iter()
+        .map(|root| microdata_item(root, &ids, base, &mut Vec::new()))
+        .collect()
+}
+
+/// Extracts RDFa Lite items (`vocab`, `prefix`, `typeof`, `property` and
+/// `resource` attributes). Types are expanded against the active vocabulary
+/// and `resource`, `href` and `src` values are resolved against `base`.
+pub fn extract_rdfa(document: &Handle, base: &Url) -> Vec<Value> {
+    let mut items = Vec::new();
+    let context = RdfaContext {
+        vocab: None,
+        prefixes: HashMap::new(),
+    };
+    walk_rdfa(document, &context, base, None, &mut items);
+    items
+}
+
+fn attr(handle: &Handle, name: &str) -> Option<String> {
+    match handle.data {
+        NodeData::Element { ref attrs, .. } => attrs
+            .borrow()
+            .iter()
+            .find(|attr| attr.name.local.as_ref() == name)
+            .map(|attr| attr.value.to_string()),
+        _ => None,
+    }
+}
+
+fn tag_name(handle: &Handle) -> Option<String> {
+    match handle.data {
+        NodeData::Element { ref name, .. } => Some(name.local.to_string()),
+        _ => None,
+    }
+}
+
+fn text_content(handle: &Handle) -> String {
+    let mut text = String::new();
+    collect_text(handle, &mut text);
+    text.trim().to_string()
+}
+
+fn collect_text(handle: &Handle, text: &mut String) {
+    if let NodeData::Text { ref contents } = handle.data {
+        text.push_str(&contents.borrow());
+    }
+    for child in handle.children.borrow().iter() {
+        collect_text(child, text);
+    }
+}
+
+fn resolve(base: &Url, value: String) -> String {
+    base.join(value.trim())
+        .map(|url| url.to_string())
+        .unwrap_or(value)
+}
+
+fn tokens(value: &str) -> Vec<String> {
+    value.split_whitespace().map(str::to_string).collect()
+}
+
+fn index_ids(handle: &Handle, ids: &mut HashMap<String, Handle>) {
+    if let Some(id) = attr(handle, "id") {
+        ids.entry(id).or_insert_with(|| handle.clone());
+    }
+    for child in handle.children.borrow().iter() {
+        index_ids(child, ids);
+    }
+}
+
+fn find_microdata_roots(handle: &Handle, roots: &mut Vec<Handle>) {
+    if attr(handle, "itemscope").is_some() && attr(handle, "itemprop").is_none() {
+        roots.push(handle.clone());
+    }
+    for child in handle.children.borrow().iter() {
+        find_microdata_roots(child, roots);
+    }
+}
+
+fn microdata_item(
+    root: &Handle,
+    ids: &HashMap<String, Handle>,
+    base: &Url,
+    stack: &mut Vec<Handle>,
+) -> Value {
+    let mut item = Map::new();
+    if let Some(types) = attr(root, "itemtype") {
+        item.insert("type".to_string(), Value::from(tokens(&types)));
+    }
+    if let Some(id) = attr(root, "itemid") {
+        item.insert("id".to_string(), Value::String(resolve(base, id)));
+    }
+
+    stack.push(root.clone());
+    let mut properties = Map::new();
+    for element in property_elements(root, ids) {
+        let Some(names) = attr(&element, "itemprop") else {
+            continue;
+        };
+        let value = if attr(&element, "itemscope").is_some() {
+            if stack.iter().any(|item| Rc::ptr_eq(item, &element)) {
+                Value::String("ERROR".to_string())
+            } else {
+                microdata_item(&element, ids, base, stack)
+            }
+        } else {
+            Value::String(microdata_value(&element, base))
+        };
+        for name in tokens(&names) {
+            if let Value::Array(values) = properties
+                .entry(name)
+                .or_insert_with(|| Value::Array(Vec::new()))
+            {
+                values.push(value.clone());
+            }
+        }
+    }
+    stack.pop();
+
+    item.insert("properties".to_string(), Value::Object(properties));
+    Value::Object(item)
+}
+
+/// Crawls the subtree of `root` and the elements referenced by its `itemref`
+/// attribute, returning every element with an `itemprop` that belongs to it.
+fn property_elements(root: &Handle, ids: &HashMap<String, Handle>) -> Vec<Handle> {
+    let mut pending: Vec<Handle> = root.children.borrow().iter().cloned().collect();
+    if let Some(refs) = attr(root, "itemref") {
+        pending.extend(tokens(&refs).iter().filter_map(|id| ids.get(id).cloned()));
+    }
+    pending.reverse();
+
+    let mut visited = HashSet::new();
+    let mut properties = Vec::new();
+    while let Some(element) = pending.pop() {
+        // Every element handed to us is kept alive by the document, so its
+        // address identifies it.
+        if Rc::ptr_eq(&element, root) || !visited.insert(Rc::as_ptr(&element)) {
+            continue;
+        }
+
+        if attr(&element, "itemprop").is_some() {
+            properties.push(element.clone());
+        }
+        if attr(&element, "itemscope").is_none() {
+            pending.extend(element.children.borrow().iter().rev().cloned());
+        }
+    }
+    properties
+}
+
+fn microdata_value(element: &Handle, base: &Url) -> String {
+    let tag = tag_name(element).unwrap_or_default();
+    let attribute = match tag.as_str() {
+        "meta" => Some("content"),
+        "audio" | "embed" | "iframe" | "img" | "source" | "track" | "video" => Some("src"),
+        "a" | "area" | "link" => Some("href"),
+        "object" => Some("data"),
+        "data" | "meter" => Some("value"),
+        "time" => Some("datetime"),
+        _ => None,
+    };
+    match attribute.and_then(|name| attr(element, name)) {
+        Some(value) if matches!(attribute, Some("src" | "href" | "data")) => resolve(base, value),
+        Some(value) => value,
+        None if tag == "time" || attribute.is_none() => text_content(element),
+        None => String::new(),
+    }
+}
+
+#[derive(Clone)]
+struct RdfaContext {
+    vocab: Option<String>,
+    prefixes: HashMap<String, String>,
+}
+
+impl RdfaContext {
+    fn expand(&self, term: &str) -> String {
+        if term.contains("://") {
+            return term.to_string();
+        }
+        if let Some((prefix, name)) = term.split_once(':') {
+            if let Some(iri) = self.prefixes.get(prefix) {
+                return format!("{}{}", iri, name);
+            }
+            return term.to_string();
+        }
+        match self.vocab {
+            Some(ref vocab) => format!("{}{}", vocab, term),
+            None => term.to_string(),
+        }
+    }
+}
+
+fn walk_rdfa(
+    handle: &Handle,
+    context: &RdfaContext,
+    base: &Url,
+    current: Option<&mut Map<String, Value>>,
+    items: &mut Vec<Value>,
+) {
+    let vocab = attr(handle, "vocab");
+    let prefix = attr(handle, "prefix");
+    let local_context;
+    let context = if vocab.is_some() || prefix.is_some() {
+        let mut context = context.clone();
+        if vocab.is_some() {
+            context.vocab = vocab;
+        }
+        if let Some(prefix) = prefix {
+            let parts = tokens(&prefix);
+            for pair in parts.chunks(2) {
+                if let [name, iri] = pair {
+                    context
+                        .prefixes
+                        .insert(name.trim_end_matches(':').to_string(), iri.clone());
+                }
+            }
+        }
+        local_context = context;
+        &local_context
+    } else {
+        context
+    };
+
+    let property = attr(handle, "property");
+    if let Some(types) = attr(handle, "typeof") {
+        let mut item = Map::new();
+        let types: Vec<String> = tokens(&types).iter().map(|t| context.expand(t)).collect();
+        item.insert("type".to_string(), Value::from(types));
+        if let Some(resource) = attr(handle, "resource") {
+            item.insert("id".to_string(), Value::String(resolve(base, resource)));
+        }
+        item.insert("properties".to_string(), Value::Object(Map::new()));
+
+        for child in handle.children.borrow().iter() {
+            walk_rdfa(child, context, base, Some(&mut item), items);
+        }
+
+        match (property, current) {
+            (Some(property), Some(parent)) => {
+                add_rdfa_property(parent, &property, Value::Object(item))
+            }
+            _ => items.push(Value::Object(item)),
+        }
+        return;
+    }
+
+    let mut current = current;
+    if let (Some(property), Some(parent)) = (property, current.as_deref_mut()) {
+        add_rdfa_property(parent, &property, Value::String(rdfa_value(handle, base)));
+    }
+    for child in handle.children.borrow().iter() {
+        walk_rdfa(child, context, base, current.as_deref_mut(), items);
+    }
+}
+
+fn add_rdfa_property(item: &mut Map<String, Value>, property: &str, value: Value) {
+    if let Some(Value::Object(properties)) = item.get_mut("properties") {
+        for name in tokens(property) {
+            if let Value::Array(values) = properties
+                .entry(name)
+                .or_insert_with(|| Value::Array(Vec::new()))
+            {
+                values.push(value.clone());
+            }
+        }
+    }
+}
+
+fn rdfa_value(element: &Handle, base: &Url) -> String {
+    attr(element, "content")
+        .or_else(|| {
+            ["href", "src", "resource"]
+                .iter()
+                .find_map(|name| attr(element, name))
+                .map(|value| resolve(base, value))
+        })
+        .or_else(|| match tag_name(element).as_deref() {
+            Some("time") => attr(element, "datetime"),
+            _ => None,
+        })
+        .unwrap_or_else(|| text_content(element))
+}
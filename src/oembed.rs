@@ -0,0 +1,296 @@
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::Path;
+use thiserror::Error;
+use url::Url;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OEmbedFormat {
+    Json,
+    Xml,
+}
+
+/// An oEmbed endpoint advertised by a `<link rel="alternate">` tag.
+#[derive(Debug, Clone)]
+pub struct OEmbedLink {
+    pub href: String,
+    pub format: OEmbedFormat,
+}
+
+impl OEmbedLink {
+    /// Recognises the `type` attribute of an oEmbed discovery link.
+    pub fn format_of(mime_type: &str) -> Option<OEmbedFormat> {
+        match mime_type.trim().to_ascii_lowercase().as_str() {
+            "application/json+oembed" => Some(OEmbedFormat::Json),
+            "text/xml+oembed" | "application/xml+oembed" => Some(OEmbedFormat::Xml),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Default, Deserialize)]
+pub struct OEmbed {
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub oembed_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub provider_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub provider_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cache_age: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thumbnail_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thumbnail_width: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thumbnail_height: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub html: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub width: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub height: Option<u64>,
+}
+
+impl OEmbed {
+    /// Reads an oEmbed response. Providers are inconsistent about value
+    /// types, so numbers given as strings (and vice versa) are accepted.
+    pub fn from_json(value: &Value) -> OEmbed {
+        let text = |name: &str| match value.get(name) {
+            Some(Value::String(text)) => Some(text.clone()),
+            Some(Value::Number(number)) => Some(number.to_string()),
+            _ => None,
+        };
+        let number = |name: &str| match value.get(name) {
+            Some(Value::Number(number)) => number
+                .as_u64()
+                .or_else(|| number.as_f64().map(|number| number as u64)),
+            Some(Value::String(text)) => text.trim().parse().ok(),
+            _ => None,
+        };
+
+        OEmbed {
+            oembed_type: text("type"),
+            version: text("version"),
+            title: text("title"),
+            author_name: text("author_name"),
+            author_url: text("author_url"),
+            provider_name: text("provider_name"),
+            provider_url: text("provider_url"),
+            cache_age: number("cache_age"),
+            thumbnail_url: text("thumbnail_url"),
+            thumbnail_width: number("thumbnail_width"),
+            thumbnail_height: number("thumbnail_height"),
+            url: text("url"),
+            html: text("html"),
+            width: number("width"),
+            height: number("height"),
+        }
+    }
+
+    /// Reads an XML oEmbed response (`<oembed><type>…</type>…</oembed>`).
+    pub fn from_xml(xml: &str) -> Result<OEmbed, quick_xml::Error> {
+        let mut reader = Reader::from_str(xml);
+        reader.config_mut().trim_text(true);
+
+        let mut fields = Map::new();
+        let mut current: Option<String> = None;
+        loop {
+            match reader.read_event()? {
+                Event::Start(element) => {
+                    current = Some(String::from_utf8_lossy(element.local_name().as_ref()).into());
+                }
+                Event::Text(text) => {
+                    if let Some(name) = &current {
+                        fields.insert(name.clone(), Value::String(text.unescape()?.into_owned()));
+                    }
+                }
+                Event::CData(data) => {
+                    if let Some(name) = &current {
+                        let text = String::from_utf8_lossy(&data.into_inner()).into_owned();
+                        fields.insert(name.clone(), Value::String(text));
+                    }
+                }
+                Event::End(_) => current = None,
+                Event::Eof => break,
+                _ => {}
+            }
+        }
+
+        Ok(OEmbed::from_json(&Value::Object(fields)))
+    }
+}
+
+/// Adds the caller's `maxwidth`/`maxheight` to an oEmbed endpoint URL,
+/// replacing any values already present.
+pub fn with_max_size(mut url: Url, max_width: Option<u32>, max_height: Option<u32>) -> Url {
+    if max_width.is_none() && max_height.is_none() {
+        return url;
+    }
+    let pairs: Vec<(String, String)> = url
+        .query_pairs()
+        .filter(|(name, _)| {
+            !(max_width.is_some() && name == "maxwidth"
+                || max_height.is_some() && name == "maxheight")
+        })
+        .map(|(name, value)| (name.into_owned(), value.into_owned()))
+        .collect();
+
+    {
+        let mut query = url.query_pairs_mut();
+        query.clear().extend_pairs(pairs);
+        if let Some(max_width) = max_width {
+            query.append_pair("maxwidth", &max_width.to_string());
+        }
+        if let Some(max_height) = max_height {
+            query.append_pair("maxheight", &max_height.to_string());
+        }
+    }
+    url
+}
+
+/// An entry of the oembed.com `providers.json` registry.
+#[derive(Debug, Deserialize)]
+pub struct Provider {
+    pub provider_name: String,
+    #[serde(default)]
+    pub endpoints: Vec<ProviderEndpoint>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ProviderEndpoint {
+    pub url: String,
+    #[serde(default)]
+    pub schemes: Vec<String>,
+}
+
+#[derive(Debug, Error)]
+pub enum ProviderRegistryError {
+    #[error(transparent)]
+    IOError(#[from] io::Error),
+
+    #[error(transparent)]
+    JSONError(#[from] serde_json::Error),
+}
+
+/// Maps page URL schemes to oEmbed endpoints, for providers that don't emit
+/// discovery links on every page.
+#[derive(Debug, Default)]
+pub struct ProviderRegistry {
+    providers: Vec<Provider>,
+}
+
+impl ProviderRegistry {
+    /// Loads a registry in the oembed.com `providers.json` format.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ProviderRegistryError> {
+        let file = File::open(path)?;
+        let providers = serde_json::from_reader(BufReader::new(file))?;
+        Ok(ProviderRegistry { providers })
+    }
+
+    /// Returns the discovery link of the first endpoint with a scheme
+    /// matching `url`.
+    pub fn find(&self, url: &str) -> Option<OEmbedLink> {
+        let (provider, endpoint) = self.providers.iter().find_map(|provider| {
+            provider
+                .endpoints
+                .iter()
+                .find(|endpoint| {
+                    endpoint
+                        .schemes
+                        .iter()
+                        .any(|scheme| matches_scheme(scheme, url))
+                })
+                .map(|endpoint| (provider, endpoint))
+        })?;
+        tracing::debug!(
+            "using oEmbed provider {} for {}",
+            provider.provider_name,
+            url
+        );
+
+        let mut href = Url::parse(&endpoint.url.replace("{format}", "json")).ok()?;
+        href.query_pairs_mut()
+            .append_pair("url", url)
+            .append_pair("format", "json");
+        Some(OEmbedLink {
+            href: href.to_string(),
+            format: OEmbedFormat::Json,
+        })
+    }
+}
+
+/// Matches `url` against a provider scheme where `*` stands for any
+/// sequence of characters.
+fn matches_scheme(scheme: &str, url: &str) -> bool {
+    let mut parts = scheme.split('*');
+    let Some(first) = parts.next() else {
+        return false;
+    };
+    let Some(mut rest) = url.strip_prefix(first) else {
+        return false;
+    };
+
+    let parts: Vec<&str> = parts.collect();
+    for (index, part) in parts.iter().enumerate() {
+        if index == parts.len() - 1 {
+            return rest.ends_with(part);
+        }
+        match rest.find(part) {
+            Some(position) => rest = &rest[position + part.len()..],
+            None => return false,
+        }
+    }
+    rest.is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches_scheme() {
+        assert!(matches_scheme(
+            "https://*.youtube.com/watch*",
+            "https://www.youtube.com/watch?v=abc"
+        ));
+        assert!(matches_scheme(
+            "https://vimeo.com/*",
+            "https://vimeo.com/12345"
+        ));
+        assert!(matches_scheme(
+            "https://vimeo.com/album/*/video/*",
+            "https://vimeo.com/album/1/video/2"
+        ));
+        assert!(!matches_scheme(
+            "https://vimeo.com/album/*/video/*",
+            "https://vimeo.com/album/1"
+        ));
+        assert!(!matches_scheme(
+            "https://*.youtube.com/watch*",
+            "https://example.com/watch"
+        ));
+        assert!(matches_scheme(
+            "https://example.com/",
+            "https://example.com/"
+        ));
+        assert!(!matches_scheme(
+            "https://example.com/",
+            "https://example.com/a"
+        ));
+    }
+}
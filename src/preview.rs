@@ -0,0 +1,188 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// A value of the preview and the source it was taken from, such as
+/// `og:title` or `h1`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Sourced {
+    pub value: String,
+    pub source: String,
+}
+
+/// A summary of the page for link previews. Each value comes from the first
+/// source of its priority chain that provides one.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Preview {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<Sourced>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<Sourced>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image: Option<Sourced>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub site_name: Option<Sourced>,
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub page_type: Option<Sourced>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<Sourced>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author: Option<Sourced>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub published_time: Option<Sourced>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub theme_color: Option<Sourced>,
+}
+
+/// Parts of the document only used as fallbacks for the preview.
+#[derive(Debug, Clone, Default)]
+pub struct DocumentHints {
+    /// Text of the first `<h1>`.
+    pub h1: Option<String>,
+    /// The `<img>` with the largest declared size, or the first one.
+    pub image: Option<ImageCandidate>,
+    /// The first JSON-LD `image`, resolved against the document base.
+    pub json_ld_image: Option<String>,
+    /// `content` of the `<meta property="article:*">` tags, by lowercase
+    /// name without the prefix. The first tag of a name wins.
+    pub article: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageCandidate {
+    pub src: String,
+    /// `width` × `height`, 0 when either is missing.
+    pub area: u64,
+}
+
+impl DocumentHints {
+    pub fn add_image(&mut self, src: String, width: Option<&str>, height: Option<&str>) {
+        // Parsed as u32 so the product fits, ignoring absurd dimensions.
+        let dimension = |value: Option<&str>| {
+            value
+                .and_then(|value| value.trim().trim_end_matches("px").parse::<u32>().ok())
+                .map_or(0, u64::from)
+        };
+        let area = dimension(width).saturating_mul(dimension(height));
+        if self.image.as_ref().is_none_or(|image| area > image.area) {
+            self.image = Some(ImageCandidate { src, area });
+        }
+    }
+}
+
+/// Picks the first non-blank value of a priority chain.
+pub fn first<'a>(chain: impl IntoIterator<Item = (&'a str, Option<&'a str>)>) -> Option<Sourced> {
+    chain.into_iter().find_map(|(source, value)| {
+        let value = value?.trim();
+        (!value.is_empty()).then(|| Sourced {
+            value: value.to_string(),
+            source: source.to_string(),
+        })
+    })
+}
+
+/// A string from a JSON-LD property: the value itself, the `name` or `url`
+/// of an object, or the first item of an array.
+pub fn json_ld_text(value: &serde_json::Value) -> Option<&str> {
+    match value {
+        serde_json::Value::String(text) => Some(text),
+        serde_json::Value::Object(object) => object
+            .get("name")
+            .or_else(|| object.get("url"))
+            .and_then(json_ld_text),
+        serde_json::Value::Array(items) => items.iter().find_map(json_ld_text),
+        _ => None,
+    }
+}
+
+/// A URL from a JSON-LD property: the value itself, the `url` or
+/// `contentUrl` of an object such as an `ImageObject`, or the first item of
+/// an array.
+pub fn json_ld_url(value: &serde_json::Value) -> Option<&str> {
+    match value {
+        serde_json::Value::String(url) => Some(url),
+        serde_json::Value::Object(object) => object
+            .get("url")
+            .or_else(|| object.get("contentUrl"))
+            .and_then(json_ld_url),
+        serde_json::Value::Array(items) => items.iter().find_map(json_ld_url),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_first() {
+        assert_eq!(
+            first([
+                ("og:title", None),
+                ("title", Some("  ")),
+                ("h1", Some(" Heading "))
+            ]),
+            Some(Sourced {
+                value: "Heading".to_string(),
+                source: "h1".to_string(),
+            })
+        );
+        assert_eq!(first([("og:title", None)]), None);
+    }
+
+    #[test]
+    fn test_largest_image() {
+        let mut hints = DocumentHints::default();
+        hints.add_image("a.png".to_string(), None, None);
+        hints.add_image("b.png".to_string(), Some("100"), Some("50"));
+        hints.add_image("c.png".to_string(), Some("300px"), Some("200"));
+        hints.add_image("d.png".to_string(), Some("1000"), None);
+        assert_eq!(hints.image.unwrap().src, "c.png");
+    }
+
+    #[test]
+    fn test_huge_image() {
+        let mut hints = DocumentHints::default();
+        hints.add_image("a.png".to_string(), Some("4294967295"), Some("4294967295"));
+        hints.add_image(
+            "b.png".to_string(),
+            Some("99999999999"),
+            Some("99999999999"),
+        );
+        let image = hints.image.unwrap();
+        assert_eq!(image.src, "a.png");
+        assert_eq!(image.area, u64::from(u32::MAX) * u64::from(u32::MAX));
+    }
+
+    #[test]
+    fn test_json_ld_text() {
+        assert_eq!(json_ld_text(&json!("Jane")), Some("Jane"));
+        assert_eq!(
+            json_ld_text(&json!({ "@type": "Person", "name": "Jane" })),
+            Some("Jane")
+        );
+        assert_eq!(
+            json_ld_text(&json!([{ "@type": "ImageObject", "url": "a.png" }])),
+            Some("a.png")
+        );
+        assert_eq!(json_ld_text(&json!(42)), None);
+    }
+
+    #[test]
+    fn test_json_ld_url() {
+        assert_eq!(json_ld_url(&json!("/a.jpg")), Some("/a.jpg"));
+        assert_eq!(
+            json_ld_url(&json!({
+                "@type": "ImageObject",
+                "name": "Cover photo",
+                "url": "https://example.com/a.jpg"
+            })),
+            Some("https://example.com/a.jpg")
+        );
+        assert_eq!(
+            json_ld_url(&json!([{ "@type": "ImageObject", "contentUrl": "b.jpg" }])),
+            Some("b.jpg")
+        );
+        assert_eq!(json_ld_url(&json!({ "name": "Cover photo" })), None);
+    }
+}
@@ -0,0 +1,127 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Mutex;
+use tokio::sync::watch;
+
+/// Deduplicates concurrent calls: while a task runs for a key, later calls
+/// with the same key wait for it and receive a clone of its result instead
+/// of running their own.
+#[derive(Debug)]
+pub struct SingleFlight<T> {
+    calls: Mutex<HashMap<String, watch::Receiver<Option<T>>>>,
+}
+
+impl<T: Clone> SingleFlight<T> {
+    /// Runs `task` for `key`, or waits for the call already running for it.
+    ///
+    /// The task runs on the caller that started it. If that caller is
+    /// dropped before it finishes, one of the waiting callers starts over.
+    pub async fn run<F, Fut>(&self, key: &str, task: F) -> T
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = T>,
+    {
+        loop {
+            let call = {
+                let mut calls = self.calls.lock().unwrap();
+                match calls.get(key) {
+                    Some(receiver) => Err(receiver.clone()),
+                    None => {
+                        let (sender, receiver) = watch::channel(None);
+                        calls.insert(key.to_string(), receiver);
+                        Ok(sender)
+                    }
+                }
+            };
+
+            match call {
+                Ok(sender) => {
+                    let _running = Running {
+                        calls: &self.calls,
+                        key,
+                    };
+                    let value = task().await;
+                    sender.send_replace(Some(value.clone()));
+                    return value;
+                }
+                Err(mut receiver) => {
+                    if let Ok(value) = receiver.wait_for(Option::is_some).await {
+                        return value.clone().expect("checked by wait_for");
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<T> Default for SingleFlight<T> {
+    fn default() -> Self {
+        SingleFlight {
+            calls: Mutex::default(),
+        }
+    }
+}
+
+/// Unregisters a call once it has finished or was cancelled.
+struct Running<'a, T> {
+    calls: &'a Mutex<HashMap<String, watch::Receiver<Option<T>>>>,
+    key: &'a str,
+}
+
+impl<T> Drop for Running<'_, T> {
+    fn drop(&mut self) {
+        self.calls.lock().unwrap().remove(self.key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+    use tokio::sync::oneshot;
+
+    #[tokio::test]
+    async fn test_single_flight() {
+        let flight = SingleFlight::default();
+        let runs = AtomicUsize::new(0);
+        let (release, released) = oneshot::channel::<()>();
+        let task = || async {
+            runs.fetch_add(1, Ordering::SeqCst);
+            42
+        };
+
+        let (first, second, other, _) = tokio::join!(
+            flight.run("a", || async {
+                runs.fetch_add(1, Ordering::SeqCst);
+                released.await.unwrap();
+                1
+            }),
+            flight.run("a", task),
+            flight.run("b", || async { 2 }),
+            async { release.send(()).unwrap() },
+        );
+        assert_eq!((first, second, other), (1, 1, 2));
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+
+        // Later calls start afresh.
+        assert_eq!(flight.run("a", task).await, 42);
+    }
+
+    #[tokio::test]
+    async fn test_single_flight_cancelled() {
+        let flight = SingleFlight::default();
+
+        // The first call is dropped when it times out, so the waiting one
+        // runs its own task.
+        let (cancelled, waiting) = tokio::join!(
+            tokio::time::timeout(
+                Duration::from_millis(10),
+                flight.run("a", std::future::pending::<u32>)
+            ),
+            flight.run("a", || async { 2 }),
+        );
+        assert!(cancelled.is_err());
+        assert_eq!(waiting, 2);
+    }
+}
@@ -0,0 +1,297 @@
+use axum::body::Bytes;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::cache::{CacheStore, CachedPage, Validators};
+
+pub const DEFAULT_MAX_SIZE: u64 = 100 * 1024 * 1024;
+
+/// Bumped whenever the table layout changes; older tables are dropped.
+const SCHEMA_VERSION: i64 = 3;
+
+/// Keeps cached results in an SQLite database, so they survive restarts and
+/// can be shared by several processes on the same host. Once the stored
+/// bodies exceed `max_size` bytes, the least recently used entries are
+/// evicted. Their total size is kept up to date by triggers, so that it is
+/// shared by every process too.
+///
+/// Calls block on disk and on locks held by other processes; they are made
+/// from the blocking thread pool by [`crate::cache::MetadataCache`].
+#[derive(Debug)]
+pub struct SqliteStore {
+    connection: Mutex<Connection>,
+    max_size: u64,
+}
+
+impl SqliteStore {
+    pub fn open(path: impl AsRef<Path>, max_size: u64) -> rusqlite::Result<Self> {
+        let mut connection = Connection::open(path)?;
+        connection.busy_timeout(Duration::from_secs(5))?;
+        connection.pragma_update_and_check(None, "journal_mode", "WAL", |_| Ok(()))?;
+
+        let transaction = connection.transaction()?;
+        let version: i64 =
+            transaction.pragma_query_value(None, "user_version", |row| row.get(0))?;
+        if version != SCHEMA_VERSION {
+            transaction.execute_batch(
+                "DROP TABLE IF EXISTS pages;
+                 DROP TABLE IF EXISTS pages_size;
+                 CREATE TABLE pages (
+                     key TEXT PRIMARY KEY,
+                     body BLOB NOT NULL,
+                     fresh_until INTEGER NOT NULL,
+                     stale_until INTEGER NOT NULL,
+                     etag TEXT,
+                     last_modified TEXT,
+                     accessed_at INTEGER NOT NULL
+                 );
+                 CREATE INDEX pages_accessed_at ON pages (accessed_at);
+                 CREATE INDEX pages_stale_until ON pages (stale_until);
+                 CREATE TABLE pages_size (total INTEGER NOT NULL);
+                 INSERT INTO pages_size VALUES (0);
+                 CREATE TRIGGER pages_inserted AFTER INSERT ON pages BEGIN
+                     UPDATE pages_size SET total = total + length(NEW.body);
+                 END;
+                 CREATE TRIGGER pages_updated AFTER UPDATE OF body ON pages BEGIN
+                     UPDATE pages_size SET total = total - length(OLD.body) + length(NEW.body);
+                 END;
+                 CREATE TRIGGER pages_deleted AFTER DELETE ON pages BEGIN
+                     UPDATE pages_size SET total = total - length(OLD.body);
+                 END;",
+            )?;
+            transaction.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+        }
+        transaction.commit()?;
+
+        Ok(SqliteStore {
+            connection: Mutex::new(connection),
+            max_size,
+        })
+    }
+
+    fn try_get(&self, key: &str) -> rusqlite::Result<Option<CachedPage>> {
+        let connection = self.connection.lock().unwrap();
+        let page = connection
+            .query_row(
+                "SELECT body, fresh_until, stale_until, etag, last_modified
+                 FROM pages WHERE key = ?1",
+                params![key],
+                |row| {
+                    Ok(CachedPage {
+                        body: Bytes::from(row.get::<_, Vec<u8>>(0)?),
+                        fresh_until: from_millis(row.get(1)?),
+                        stale_until: from_millis(row.get(2)?),
+                        validators: Validators {
+                            etag: row.get(3)?,
+                            last_modified: row.get(4)?,
+                        },
+                    })
+                },
+            )
+            .optional()?;
+        if page.is_some() {
+            connection.execute(
+                "UPDATE pages SET accessed_at = ?2 WHERE key = ?1",
+                params![key, to_millis(SystemTime::now())],
+            )?;
+        }
+        Ok(page)
+    }
+
+    fn try_put(&self, key: &str, page: &CachedPage) -> rusqlite::Result<()> {
+        let mut connection = self.connection.lock().unwrap();
+        let now = to_millis(SystemTime::now());
+        let transaction = connection.transaction()?;
+        // An upsert rather than `INSERT OR REPLACE`, whose implicit delete
+        // would not fire the size triggers.
+        transaction.execute(
+            "INSERT INTO pages
+                 (key, body, fresh_until, stale_until, etag, last_modified, accessed_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+             ON CONFLICT (key) DO UPDATE SET
+                 body = excluded.body,
+                 fresh_until = excluded.fresh_until,
+                 stale_until = excluded.stale_until,
+                 etag = excluded.etag,
+                 last_modified = excluded.last_modified,
+                 accessed_at = excluded.accessed_at",
+            params![
+                key,
+                page.body.as_ref(),
+                to_millis(page.fresh_until),
+                to_millis(page.stale_until),
+                page.validators.etag,
+                page.validators.last_modified,
+                now
+            ],
+        )?;
+        transaction.execute("DELETE FROM pages WHERE stale_until <= ?1", params![now])?;
+
+        let total: i64 =
+            transaction.query_row("SELECT total FROM pages_size", [], |row| row.get(0))?;
+        let mut excess = total - self.max_size as i64;
+        if excess > 0 {
+            // Evict the least recently used entries until the rest fits.
+            let mut keys = Vec::new();
+            {
+                let mut statement = transaction
+                    .prepare("SELECT key, length(body) FROM pages ORDER BY accessed_at, key")?;
+                let mut rows = statement.query([])?;
+                while excess > 0 {
+                    let Some(row) = rows.next()? else {
+                        break;
+                    };
+                    keys.push(row.get::<_, String>(0)?);
+                    excess -= row.get::<_, i64>(1)?;
+                }
+            }
+            let mut delete = transaction.prepare("DELETE FROM pages WHERE key = ?1")?;
+            for key in keys {
+                delete.execute(params![key])?;
+            }
+        }
+        transaction.commit()
+    }
+
+    fn try_remove(&self, key: &str) -> rusqlite::Result<()> {
+        self.connection
+            .lock()
+            .unwrap()
+            .execute("DELETE FROM pages WHERE key = ?1", params![key])?;
+        Ok(())
+    }
+}
+
+impl CacheStore for SqliteStore {
+    fn get(&self, key: &str) -> Option<CachedPage> {
+        self.try_get(key).unwrap_or_else(|err| {
+            tracing::warn!("failed to read cache entry {}: {}", key, err);
+            None
+        })
+    }
+
+    fn put(&self, key: &str, page: CachedPage) {
+        if let Err(err) = self.try_put(key, &page) {
+            tracing::warn!("failed to write cache entry {}: {}", key, err);
+        }
+    }
+
+    fn remove(&self, key: &str) {
+        if let Err(err) = self.try_remove(key) {
+            tracing::warn!("failed to remove cache entry {}: {}", key, err);
+        }
+    }
+}
+
+fn to_millis(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as i64
+}
+
+fn from_millis(millis: i64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_millis(millis.max(0) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(body: &'static str) -> CachedPage {
+        let now = SystemTime::now();
+        CachedPage {
+            body: Bytes::from(body),
+            fresh_until: now + Duration::from_secs(60),
+            stale_until: now + Duration::from_secs(120),
+            validators: Validators {
+                etag: Some(format!("\"{}\"", body)),
+                last_modified: None,
+            },
+        }
+    }
+
+    #[test]
+    fn test_sqlite_store() {
+        let path =
+            std::env::temp_dir().join(format!("pages-meta-cache-{}.sqlite", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        {
+            let store = SqliteStore::open(&path, 10).unwrap();
+            store.put("a", page("aaaa"));
+            store.put("b", page("bbbb"));
+            assert_eq!(store.get("a").unwrap().body, Bytes::from("aaaa"));
+
+            // "a" was read last, so "b" makes way for "c".
+            std::thread::sleep(Duration::from_millis(5));
+            store.get("a");
+            store.put("c", page("cccc"));
+            assert!(store.get("b").is_none());
+            assert!(store.get("c").is_some());
+
+            store.remove("c");
+            assert!(store.get("c").is_none());
+        }
+
+        // Entries survive reopening the database.
+        let store = SqliteStore::open(&path, 10).unwrap();
+        let cached = store.get("a").unwrap();
+        assert_eq!(cached.body, Bytes::from("aaaa"));
+        assert!(cached.fresh_until > SystemTime::now());
+        assert_eq!(cached.validators.etag.as_deref(), Some("\"aaaa\""));
+
+        drop(store);
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{}", path.display(), suffix));
+        }
+    }
+
+    #[test]
+    fn test_sqlite_store_size() {
+        let path = std::env::temp_dir().join(format!(
+            "pages-meta-cache-size-{}.sqlite",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+
+        let store = SqliteStore::open(&path, 10).unwrap();
+        let total = || -> (i64, i64) {
+            let connection = store.connection.lock().unwrap();
+            let total = connection
+                .query_row("SELECT total FROM pages_size", [], |row| row.get(0))
+                .unwrap();
+            let actual = connection
+                .query_row(
+                    "SELECT COALESCE(SUM(length(body)), 0) FROM pages",
+                    [],
+                    |row| row.get(0),
+                )
+                .unwrap();
+            (total, actual)
+        };
+
+        store.put("a", page("aaaa"));
+        std::thread::sleep(Duration::from_millis(5));
+        store.put("b", page("bb"));
+        assert_eq!(total(), (6, 6));
+        // Replacing an entry counts only its new body.
+        std::thread::sleep(Duration::from_millis(5));
+        store.put("a", page("a"));
+        assert_eq!(total(), (3, 3));
+        // Evicting "b", the least recently used entry, is enough.
+        std::thread::sleep(Duration::from_millis(5));
+        store.put("c", page("cccccccc"));
+        assert_eq!(total(), (9, 9));
+        assert!(store.get("a").is_some());
+        assert!(store.get("b").is_none());
+        store.remove("c");
+        assert_eq!(total(), (1, 1));
+
+        drop(store);
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{}", path.display(), suffix));
+        }
+    }
+}
@@ -0,0 +1,191 @@
+use ipnet::IpNet;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use std::error::Error as StdError;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::OnceLock;
+use thiserror::Error;
+use url::{Host, Url};
+
+/// Private, loopback, link-local and otherwise reserved ranges that outgoing
+/// requests must never reach.
+const BLOCKED_RANGES: [&str; 24] = [
+    "0.0.0.0/8",
+    "10.0.0.0/8",
+    "100.64.0.0/10",
+    "127.0.0.0/8",
+    "169.254.0.0/16",
+    "172.16.0.0/12",
+    "192.0.0.0/24",
+    "192.0.2.0/24",
+    "192.88.99.0/24",
+    "192.168.0.0/16",
+    "198.18.0.0/15",
+    "198.51.100.0/24",
+    "203.0.113.0/24",
+    "224.0.0.0/4",
+    "240.0.0.0/4",
+    "::/128",
+    "::1/128",
+    "100::/64",
+    "2001::/23",
+    "2001:db8::/32",
+    "fc00::/7",
+    "fe80::/10",
+    "fec0::/10",
+    "ff00::/8",
+];
+
+fn blocked_ranges() -> &'static [IpNet] {
+    static RANGES: OnceLock<Vec<IpNet>> = OnceLock::new();
+    RANGES.get_or_init(|| {
+        BLOCKED_RANGES
+            .iter()
+            .map(|range| range.parse().unwrap())
+            .collect()
+    })
+}
+
+#[derive(Debug, Clone, Error)]
+#[error("access to {host} ({address}) is not allowed")]
+pub struct BlockedAddressError {
+    pub host: String,
+    pub address: IpAddr,
+}
+
+/// Rejects outgoing connections to private and reserved addresses, both for
+/// literal IP hosts and for names after DNS resolution. Ranges listed in
+/// `allowlist` are exempt, for deployments that must reach internal hosts.
+#[derive(Debug, Default)]
+pub struct AddressGuard {
+    allowlist: Vec<IpNet>,
+}
+
+impl AddressGuard {
+    pub fn new(allowlist: Vec<IpNet>) -> Self {
+        AddressGuard { allowlist }
+    }
+
+    /// Parses a comma-separated list of CIDR ranges or single addresses.
+    pub fn parse_allowlist(allowlist: &str) -> Result<Vec<IpNet>, ipnet::AddrParseError> {
+        allowlist
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| {
+                entry
+                    .parse::<IpNet>()
+                    .or_else(|err| entry.parse::<IpAddr>().map(IpNet::from).map_err(|_| err))
+            })
+            .collect()
+    }
+
+    pub fn is_blocked(&self, address: IpAddr) -> bool {
+        if self.allowlist.iter().any(|range| range.contains(&address)) {
+            return false;
+        }
+        // IPv4-mapped and NAT64 addresses are judged by the IPv4 address
+        // they embed.
+        if let IpAddr::V6(v6) = address {
+            if let Some(v4) = embedded_ipv4(v6) {
+                return self.is_blocked(IpAddr::V4(v4));
+            }
+        }
+        blocked_ranges()
+            .iter()
+            .any(|range| range.contains(&address))
+    }
+
+    /// Checks a URL whose host is an IP literal; names are checked by the
+    /// resolver when the request is made.
+    pub fn check_url(&self, url: &Url) -> Result<(), BlockedAddressError> {
+        let address = match url.host() {
+            Some(Host::Ipv4(v4)) => IpAddr::V4(v4),
+            Some(Host::Ipv6(v6)) => IpAddr::V6(v6),
+            _ => return Ok(()),
+        };
+        if self.is_blocked(address) {
+            return Err(BlockedAddressError {
+                host: url.host_str().unwrap_or_default().to_string(),
+                address,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl Resolve for AddressGuard {
+    fn resolve(&self, name: Name) -> Resolving {
+        let allowlist = self.allowlist.clone();
+        Box::pin(async move {
+            let guard = AddressGuard { allowlist };
+            let host = name.as_str().to_string();
+            let addresses: Vec<SocketAddr> =
+                tokio::net::lookup_host((host.as_str(), 0)).await?.collect();
+
+            let allowed: Vec<SocketAddr> = addresses
+                .iter()
+                .filter(|address| !guard.is_blocked(address.ip()))
+                .copied()
+                .collect();
+            if allowed.is_empty() {
+                if let Some(address) = addresses.first() {
+                    let err: Box<dyn StdError + Send + Sync> = Box::new(BlockedAddressError {
+                        host,
+                        address: address.ip(),
+                    });
+                    return Err(err);
+                }
+            }
+            let addrs: Addrs = Box::new(allowed.into_iter());
+            Ok(addrs)
+        })
+    }
+}
+
+fn embedded_ipv4(address: Ipv6Addr) -> Option<Ipv4Addr> {
+    let segments = address.segments();
+    match segments {
+        [0, 0, 0, 0, 0, 0xffff, ..] | [0x64, 0xff9b, 0, 0, 0, 0, ..] => Some(Ipv4Addr::new(
+            (segments[6] >> 8) as u8,
+            segments[6] as u8,
+            (segments[7] >> 8) as u8,
+            segments[7] as u8,
+        )),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_blocked() {
+        let guard = AddressGuard::default();
+        for address in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "fe80::1",
+            "fd00::1",
+            "::ffff:127.0.0.1",
+            "64:ff9b::a9fe:a9fe",
+        ] {
+            assert!(guard.is_blocked(address.parse().unwrap()), "{}", address);
+        }
+        for address in ["93.184.216.34", "2606:2800:220:1:248:1893:25c8:1946"] {
+            assert!(!guard.is_blocked(address.parse().unwrap()), "{}", address);
+        }
+
+        let guard =
+            AddressGuard::new(AddressGuard::parse_allowlist("10.0.0.0/8, 127.0.0.1").unwrap());
+        assert!(!guard.is_blocked("10.1.2.3".parse().unwrap()));
+        assert!(!guard.is_blocked("127.0.0.1".parse().unwrap()));
+        assert!(guard.is_blocked("127.0.0.2".parse().unwrap()));
+    }
+}
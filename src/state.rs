@@ -0,0 +1,352 @@
+use axum::body::Bytes;
+use reqwest::{
+    header::{self, HeaderMap},
+    redirect, Method, RequestBuilder, Response, StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, Weak},
+    time::Duration,
+};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use url::{Host, Url};
+
+use crate::{
+    cache::MetadataCache,
+    errors::ServerError,
+    handlers::Extraction,
+    host_policy::HostPolicyStore,
+    jobs::JobQueue,
+    oembed::ProviderRegistry,
+    single_flight::SingleFlight,
+    ssrf::{AddressGuard, BlockedAddressError},
+};
+
+pub const DEFAULT_MAX_BODY_SIZE: usize = 5 * 1024 * 1024;
+
+const REDIRECT_STATUSES: [StatusCode; 5] = [
+    StatusCode::MOVED_PERMANENTLY,
+    StatusCode::FOUND,
+    StatusCode::SEE_OTHER,
+    StatusCode::TEMPORARY_REDIRECT,
+    StatusCode::PERMANENT_REDIRECT,
+];
+
+/// Limits of `POST /batch`.
+#[derive(Debug, Clone)]
+pub struct BatchConfig {
+    /// Most URLs accepted in one batch.
+    pub max_size: usize,
+    /// Extractions of all batches running at the same time.
+    pub parallelism: usize,
+    /// Extractions of all batches running at the same time against one host.
+    pub host_parallelism: usize,
+}
+
+impl Default for BatchConfig {
+    fn default() -> Self {
+        BatchConfig {
+            max_size: 1000,
+            parallelism: 16,
+            host_parallelism: 2,
+        }
+    }
+}
+
+/// Hands out the slots batch extractions run in, shared by every batch so
+/// that concurrent batches stay within the configured limits.
+#[derive(Debug)]
+pub struct BatchLimiter {
+    config: BatchConfig,
+    parallelism: Arc<Semaphore>,
+    /// Semaphores of the hosts with extractions waiting or running.
+    hosts: Mutex<HashMap<String, Weak<Semaphore>>>,
+}
+
+impl BatchLimiter {
+    pub fn new(config: BatchConfig) -> Self {
+        BatchLimiter {
+            parallelism: Arc::new(Semaphore::new(config.parallelism)),
+            hosts: Mutex::default(),
+            config,
+        }
+    }
+
+    pub fn max_size(&self) -> usize {
+        self.config.max_size
+    }
+
+    /// Waits for a slot against `host`, then for a slot overall. Waiting for
+    /// the host first keeps a busy host from holding slots that other hosts
+    /// could use.
+    pub async fn acquire(&self, host: &str) -> (OwnedSemaphorePermit, OwnedSemaphorePermit) {
+        let host = {
+            let mut hosts = self.hosts.lock().unwrap();
+            hosts.retain(|_, semaphore| semaphore.strong_count() > 0);
+            match hosts.get(host).and_then(Weak::upgrade) {
+                Some(semaphore) => semaphore,
+                None => {
+                    let semaphore = Arc::new(Semaphore::new(self.config.host_parallelism));
+                    hosts.insert(host.to_string(), Arc::downgrade(&semaphore));
+                    semaphore
+                }
+            }
+        };
+        let host_permit = host
+            .acquire_owned()
+            .await
+            .expect("batch semaphores are never closed");
+        let permit = self
+            .parallelism
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("batch semaphores are never closed");
+        (host_permit, permit)
+    }
+}
+
+impl Default for BatchLimiter {
+    fn default() -> Self {
+        BatchLimiter::new(BatchConfig::default())
+    }
+}
+
+/// A redirect response received while fetching a URL.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Redirect {
+    /// The URL that answered with the redirect.
+    pub url: String,
+    pub status: u16,
+    /// The `Location` header, resolved against `url`.
+    pub location: String,
+}
+
+/// HTTP versions the client may use for outgoing requests.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HttpVersion {
+    /// HTTP/2 when the server offers it through ALPN, HTTP/1.1 otherwise.
+    #[default]
+    Auto,
+    /// HTTP/1.1 only.
+    Http1,
+    /// HTTP/2 only, without negotiation.
+    Http2,
+}
+
+/// Settings of the HTTP client shared by all outgoing requests.
+#[derive(Debug, Clone)]
+pub struct HttpClientConfig {
+    pub user_agent: String,
+    pub connect_timeout: Duration,
+    pub read_timeout: Duration,
+    pub max_redirects: usize,
+    /// Proxy for all outgoing requests. The proxy resolves host names
+    /// itself, so they are resolved and checked by the address guard before
+    /// each request instead.
+    pub proxy: Option<String>,
+    pub http_version: HttpVersion,
+    pub default_headers: HeaderMap,
+}
+
+impl Default for HttpClientConfig {
+    fn default() -> Self {
+        HttpClientConfig {
+            user_agent: concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION")).to_string(),
+            connect_timeout: Duration::from_secs(5),
+            read_timeout: Duration::from_secs(10),
+            max_redirects: 10,
+            proxy: None,
+            http_version: HttpVersion::Auto,
+            default_headers: HeaderMap::new(),
+        }
+    }
+}
+
+/// Shared state of the application, built once at startup.
+#[derive(Debug)]
+pub struct AppState {
+    pub oembed_providers: ProviderRegistry,
+    pub address_guard: Arc<AddressGuard>,
+    pub host_policy: Arc<HostPolicyStore>,
+    /// Longest page body read before the extraction is aborted.
+    pub max_body_size: usize,
+    pub cache: MetadataCache,
+    pub batch: BatchLimiter,
+    pub jobs: JobQueue,
+    /// Extractions under way, by cache key.
+    pub extractions: SingleFlight<Result<Extraction, Arc<ServerError>>>,
+    http_client: reqwest::Client,
+    max_redirects: usize,
+    /// Requests go through a proxy.
+    proxied: bool,
+}
+
+impl AppState {
+    /// Builds the state around an HTTP client that refuses to connect to
+    /// private and reserved addresses. Redirects are followed by
+    /// [`AppState::follow`], which also checks every hop.
+    pub fn new(
+        address_guard: AddressGuard,
+        host_policy: HostPolicyStore,
+        config: &HttpClientConfig,
+    ) -> Result<Self, reqwest::Error> {
+        let address_guard = Arc::new(address_guard);
+        let mut builder = reqwest::Client::builder()
+            .dns_resolver(address_guard.clone())
+            .redirect(redirect::Policy::none())
+            .user_agent(&config.user_agent)
+            .connect_timeout(config.connect_timeout)
+            .read_timeout(config.read_timeout)
+            .default_headers(config.default_headers.clone())
+            // Proxies from `HTTP_PROXY` and friends would resolve host names
+            // behind the address guard's back; only an explicit one is used.
+            .no_proxy();
+        if let Some(proxy) = &config.proxy {
+            builder = builder.proxy(reqwest::Proxy::all(proxy)?);
+        }
+        builder = match config.http_version {
+            HttpVersion::Auto => builder,
+            HttpVersion::Http1 => builder.http1_only(),
+            HttpVersion::Http2 => builder.http2_prior_knowledge(),
+        };
+
+        Ok(AppState {
+            oembed_providers: ProviderRegistry::default(),
+            address_guard,
+            host_policy: Arc::new(host_policy),
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+            cache: MetadataCache::default(),
+            batch: BatchLimiter::default(),
+            jobs: JobQueue::default(),
+            extractions: SingleFlight::default(),
+            http_client: builder.build()?,
+            max_redirects: config.max_redirects,
+            proxied: config.proxy.is_some(),
+        })
+    }
+
+    /// Checks `url` against the address guard and the host policy. Names are
+    /// only checked by the guard once they are resolved.
+    pub fn check_url(&self, url: &Url) -> Result<(), ServerError> {
+        self.address_guard.check_url(url)?;
+        self.host_policy.check(url)?;
+        Ok(())
+    }
+
+    /// Starts an outgoing request after checking `url` against the address
+    /// guard and the host policy.
+    async fn request(&self, method: Method, url: &Url) -> Result<RequestBuilder, ServerError> {
+        self.check_url(url)?;
+        self.check_proxied_host(url).await?;
+        Ok(self.http_client.request(method, url.clone()))
+    }
+
+    /// Behind a proxy, host names never reach the guarded resolver, so they
+    /// are resolved here and refused when any of their addresses is blocked.
+    /// The proxy resolves them again, so a name whose addresses change in
+    /// between is not caught.
+    async fn check_proxied_host(&self, url: &Url) -> Result<(), ServerError> {
+        let Some(Host::Domain(host)) = url.host().filter(|_| self.proxied) else {
+            return Ok(());
+        };
+        let port = url.port_or_known_default().unwrap_or_default();
+        for address in tokio::net::lookup_host((host, port)).await? {
+            if self.address_guard.is_blocked(address.ip()) {
+                return Err(BlockedAddressError {
+                    host: host.to_string(),
+                    address: address.ip(),
+                }
+                .into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Sends a request, following redirects up to the configured limit,
+    /// past which it fails.
+    pub async fn send(&self, method: Method, url: &str) -> Result<Response, ServerError> {
+        let (response, redirects) = self.follow(method, url, HeaderMap::new(), None).await?;
+        if redirects.len() > self.max_redirects {
+            return Err(ServerError::TooManyRedirects(self.max_redirects));
+        }
+        Ok(response)
+    }
+
+    /// Posts `body` to `url` without following redirects.
+    pub async fn post(
+        &self,
+        url: &str,
+        headers: HeaderMap,
+        body: Bytes,
+    ) -> Result<Response, ServerError> {
+        Ok(self
+            .request(Method::POST, &Url::parse(url)?)
+            .await?
+            .headers(headers)
+            .body(body)
+            .send()
+            .await?)
+    }
+
+    /// Sends a request with `headers` and follows up to `max_redirects`
+    /// redirects (never more than the configured limit), checking every hop
+    /// against the address guard and the host policy. Once the limit is
+    /// reached, the redirect response itself is returned. All redirects
+    /// received are reported, including that last one.
+    pub async fn follow(
+        &self,
+        method: Method,
+        url: &str,
+        headers: HeaderMap,
+        max_redirects: Option<usize>,
+    ) -> Result<(Response, Vec<Redirect>), ServerError> {
+        let max_redirects = max_redirects.map_or(self.max_redirects, |max_redirects| {
+            max_redirects.min(self.max_redirects)
+        });
+        let mut url = Url::parse(url)?;
+        let mut redirects = Vec::new();
+        loop {
+            let response = self
+                .request(method.clone(), &url)
+                .await?
+                .headers(headers.clone())
+                .send()
+                .await?;
+            if !REDIRECT_STATUSES.contains(&response.status()) {
+                return Ok((response, redirects));
+            }
+            let Some(location) = response
+                .headers()
+                .get(header::LOCATION)
+                .and_then(|location| location.to_str().ok())
+                .and_then(|location| url.join(location).ok())
+            else {
+                return Ok((response, redirects));
+            };
+
+            redirects.push(Redirect {
+                url: url.to_string(),
+                status: response.status().as_u16(),
+                location: location.to_string(),
+            });
+            if redirects.len() > max_redirects {
+                return Ok((response, redirects));
+            }
+            url = location;
+        }
+    }
+}
+
+impl Default for AppState {
+    fn default() -> Self {
+        AppState::new(
+            AddressGuard::default(),
+            HostPolicyStore::default(),
+            &HttpClientConfig::default(),
+        )
+        .expect("failed to build HTTP client")
+    }
+}